@@ -0,0 +1,163 @@
+use map::{Map, MapPos, PosState};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// один из кратчайших путей и его стоимость
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alternative {
+    pub cost: f64,
+    pub path: Vec<MapPos>,
+}
+
+// стоимость пути по карте
+fn path_cost(map: &Map, path: &[MapPos]) -> f64 {
+    path.windows(2).map(|w| map.cost(w[0], w[1])).sum()
+}
+
+// алгоритм Дейкстры от клетки до ближайшей конечной клетки,
+// запрещённые клетки и переходы не используются
+fn dijkstra(
+    map: &Map,
+    from: MapPos,
+    nodes: &HashSet<MapPos>,
+    edges: &HashSet<(MapPos, MapPos)>,
+) -> Option<Alternative> {
+    let mut costs = HashMap::new();
+    let mut parents = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    costs.insert(from, 0f64);
+    queue.push(PosState {
+        pos: from,
+        cost: 0f64,
+    });
+    while let Some(state) = queue.pop() {
+        if state.cost > costs[&state.pos] {
+            continue;
+        }
+        if map.finishes().contains(&state.pos) {
+            let mut path = vec![state.pos];
+            let mut pos = state.pos;
+            while let Some(parent) = parents.get(&pos) {
+                path.push(*parent);
+                pos = *parent;
+            }
+            path.reverse();
+            return Some(Alternative {
+                cost: state.cost,
+                path,
+            });
+        }
+        for next in map.neighbors(state.pos) {
+            if nodes.contains(&next) || edges.contains(&(state.pos, next)) {
+                continue;
+            }
+            let cost = state.cost + map.cost(state.pos, next);
+            if costs.get(&next).is_none_or(|c| cost < *c) {
+                costs.insert(next, cost);
+                parents.insert(next, state.pos);
+                queue.push(PosState { pos: next, cost });
+            }
+        }
+    }
+    None
+}
+
+// k кратчайших путей без циклов от начальной клетки до конечных
+// (алгоритм Йена), пути упорядочены по возрастанию стоимости
+pub fn k_shortest(map: &Map, k: usize) -> Vec<Alternative> {
+    let mut found = Vec::with_capacity(k);
+    let first = match dijkstra(map, map.start(), &HashSet::new(), &HashSet::new()) {
+        Some(first) if k > 0 => first,
+        _ => return found,
+    };
+    found.push(first);
+    let mut candidates: Vec<Alternative> = Vec::new();
+    while found.len() < k {
+        let prev = found[found.len() - 1].path.clone();
+        // ответвление от каждой клетки предыдущего пути, кроме конечной
+        for i in 0..prev.len() - 1 {
+            let spur = prev[i];
+            let root = &prev[..=i];
+            // переходы, которыми уже найденные пути продолжают общий корень
+            let edges = found
+                .iter()
+                .filter(|a| a.path.len() > i + 1 && a.path[..=i] == *root)
+                .map(|a| (a.path[i], a.path[i + 1]))
+                .collect::<HashSet<_>>();
+            // клетки корня, кроме точки ответвления, исключаются для отсутствия циклов
+            let nodes = root[..i].iter().cloned().collect::<HashSet<_>>();
+            if let Some(spur_path) = dijkstra(map, spur, &nodes, &edges) {
+                let mut path = root[..i].to_vec();
+                path.extend(spur_path.path);
+                let candidate = Alternative {
+                    cost: path_cost(map, &path),
+                    path,
+                };
+                let known = candidates.iter().chain(found.iter());
+                if !known.map(|a| &a.path).any(|p| *p == candidate.path) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        let best = (0..candidates.len()).min_by(|a, b| {
+            candidates[*a]
+                .cost
+                .partial_cmp(&candidates[*b].cost)
+                .unwrap()
+        });
+        match best {
+            Some(best) => found.push(candidates.swap_remove(best)),
+            None => break,
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{Cell, SearchStatus, ShortestPath};
+    use search::BestFirst;
+
+    fn dijkstra_cost(map: &Map) -> Option<f64> {
+        match ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra())).run() {
+            SearchStatus::Found(cost) => Some(cost),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn paths_are_ordered_and_loopless() {
+        let map = format::from_text("S..#....\n.#.#.##.\n.#...#..\n...#...F\n").unwrap();
+        let found = k_shortest(&map, 8);
+        assert_eq!(found.len(), 8);
+        assert!((found[0].cost - dijkstra_cost(&map).unwrap()).abs() < 1e-9);
+        for (i, alternative) in found.iter().enumerate() {
+            let path = &alternative.path;
+            assert_eq!(path[0], map.start());
+            assert_eq!(path[path.len() - 1], map.finishes()[0]);
+            for w in path.windows(2) {
+                assert!(map.neighbors(w[0]).any(|p| p == w[1]));
+            }
+            assert!((alternative.cost - path_cost(&map, path)).abs() < 1e-9);
+            // клетки пути не повторяются, пути не совпадают друг с другом
+            let cells = path.iter().collect::<HashSet<_>>();
+            assert_eq!(cells.len(), path.len());
+            assert!(found[..i].iter().all(|a| a.path != *path));
+            if i > 0 {
+                assert!(found[i - 1].cost <= alternative.cost + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_when_none_requested_or_unreachable() {
+        let mut map = format::from_text("S.#..\n..#.F\n").unwrap();
+        assert!(k_shortest(&map, 0).is_empty());
+        assert!(k_shortest(&map, 3).is_empty());
+        assert_eq!(dijkstra_cost(&map), None);
+        map.set_cell(Cell::Passable, MapPos::new(0, 2));
+        assert!(k_shortest(&map, 0).is_empty());
+        assert_eq!(k_shortest(&map, 1).len(), 1);
+    }
+}
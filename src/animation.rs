@@ -0,0 +1,196 @@
+use cairo::{Context, Format, ImageSurface};
+use gif::{self, SetParameter};
+use map::{SearchStatus, ShortestPath};
+use render::{RenderError, Renderer, EXPORT_CELL_SIZE};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+// параметры записи анимации поиска
+#[derive(Debug, Clone)]
+pub struct AnimationOptions {
+    // количество итераций поиска между кадрами
+    pub steps_per_frame: usize,
+    // задержка между кадрами в миллисекундах
+    pub delay: u16,
+    // размер клетки в пикселях
+    pub cell_size: usize,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            steps_per_frame: 1,
+            delay: 50,
+            cell_size: EXPORT_CELL_SIZE,
+        }
+    }
+}
+
+// ошибка записи анимации
+#[derive(Debug)]
+pub enum AnimationError {
+    Cairo(String),
+    Io(io::Error),
+    Render(RenderError),
+    Size(i32, i32),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::Cairo(err) => write!(f, "Ошибка cairo: {}", err),
+            AnimationError::Io(err) => write!(f, "{}", err),
+            AnimationError::Render(err) => write!(f, "{}", err),
+            AnimationError::Size(width, height) => {
+                write!(f, "Размер кадра {}x{} слишком велик для GIF", width, height)
+            }
+        }
+    }
+}
+
+impl Error for AnimationError {}
+
+impl From<io::Error> for AnimationError {
+    fn from(err: io::Error) -> Self {
+        AnimationError::Io(err)
+    }
+}
+
+impl From<RenderError> for AnimationError {
+    fn from(err: RenderError) -> Self {
+        AnimationError::Render(err)
+    }
+}
+
+// получатель кадров анимации
+trait FrameSink {
+    fn frame(&mut self, surface: &mut ImageSurface) -> Result<(), AnimationError>;
+}
+
+// анимированный GIF
+struct GifSink {
+    encoder: gif::Encoder<File>,
+    width: u16,
+    height: u16,
+    delay: u16,
+}
+
+impl FrameSink for GifSink {
+    fn frame(&mut self, surface: &mut ImageSurface) -> Result<(), AnimationError> {
+        let stride = surface.get_stride() as usize;
+        let data = surface
+            .get_data()
+            .map_err(|err| AnimationError::Cairo(format!("{:?}", err)))?;
+        // cairo хранит пиксели ARGB32 в порядке байт платформы
+        let mut pixels = Vec::with_capacity(usize::from(self.width) * usize::from(self.height) * 3);
+        for row in data.chunks(stride).take(usize::from(self.height)) {
+            for pixel in row.chunks(4).take(usize::from(self.width)) {
+                let argb = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                pixels.push((argb >> 16) as u8);
+                pixels.push((argb >> 8) as u8);
+                pixels.push(argb as u8);
+            }
+        }
+        let mut frame = gif::Frame::from_rgb_speed(self.width, self.height, &pixels, 10);
+        // задержка в GIF задаётся в сотых долях секунды
+        frame.delay = self.delay / 10;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+// последовательность пронумерованных PNG файлов в каталоге
+struct PngSink<'a> {
+    dir: &'a Path,
+    index: usize,
+}
+
+impl<'a> FrameSink for PngSink<'a> {
+    fn frame(&mut self, surface: &mut ImageSurface) -> Result<(), AnimationError> {
+        let mut file = File::create(self.dir.join(format!("{:05}.png", self.index)))?;
+        self.index += 1;
+        surface
+            .write_to_png(&mut file)
+            .map_err(|err| AnimationError::Cairo(format!("{:?}", err)))
+    }
+}
+
+// запись анимации поиска пути: в GIF для файлов с расширением gif,
+// иначе в каталог с последовательностью PNG, возвращает количество кадров
+pub fn export(
+    search: &mut ShortestPath,
+    file: &Path,
+    options: &AnimationOptions,
+) -> Result<usize, AnimationError> {
+    let (width, height) = Renderer::new(&search.map).export_size(options.cell_size)?;
+    let is_gif = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("gif"))
+        .unwrap_or(false);
+    if is_gif {
+        if width > i32::from(u16::MAX) || height > i32::from(u16::MAX) {
+            return Err(AnimationError::Size(width, height));
+        }
+        let mut encoder = gif::Encoder::new(File::create(file)?, width as u16, height as u16, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+        let mut sink = GifSink {
+            encoder,
+            width: width as u16,
+            height: height as u16,
+            delay: options.delay,
+        };
+        record(search, &mut sink, width, height, options)
+    } else {
+        fs::create_dir_all(file)?;
+        let mut sink = PngSink {
+            dir: file,
+            index: 0,
+        };
+        record(search, &mut sink, width, height, options)
+    }
+}
+
+// выполнение поиска с отрисовкой кадра каждые steps_per_frame итераций
+fn record<S: FrameSink>(
+    search: &mut ShortestPath,
+    sink: &mut S,
+    width: i32,
+    height: i32,
+    options: &AnimationOptions,
+) -> Result<usize, AnimationError> {
+    let mut surface = ImageSurface::create(Format::ARgb32, width, height)
+        .map_err(|err| AnimationError::Cairo(format!("{:?}", err)))?;
+    let mut frames = 0;
+    let mut status = SearchStatus::Searching;
+    search.init();
+    while status == SearchStatus::Searching {
+        for _ in 0..options.steps_per_frame.max(1) {
+            status = search.next();
+            if status != SearchStatus::Searching {
+                break;
+            }
+        }
+        // последний кадр содержит найденный путь
+        let path = match status {
+            SearchStatus::Found(_) => search.path(),
+            SearchStatus::BudgetExhausted { ref path, .. } => Some(path.clone()),
+            _ => None,
+        };
+        {
+            let context = Context::new(&surface);
+            Renderer::new(&search.map).with_path(path.as_deref()).draw(
+                &context,
+                f64::from(width),
+                f64::from(height),
+            );
+        }
+        surface.flush();
+        sink.frame(&mut surface)?;
+        frames += 1;
+    }
+    Ok(frames)
+}
@@ -0,0 +1,427 @@
+use format::{self, MapFormat};
+use map::{Cell, Map, MapPos, PosState, SearchStatus, ShortestPath};
+use search::{BestFirst, Registry};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// пары начальной и конечной клеток для сравнения алгоритмов
+#[derive(Debug, Clone)]
+pub enum Pairs {
+    // случайные пары свободных клеток на каждой карте
+    Random { count: usize, seed: u64 },
+    // одни и те же пары на всех картах, пары вне карты пропускаются
+    Fixed(Vec<(MapPos, MapPos)>),
+}
+
+// результат одного запуска алгоритма
+#[derive(Debug, Clone)]
+pub struct BenchRecord {
+    pub map: String,
+    pub start: MapPos,
+    pub goal: MapPos,
+    pub algorithm: &'static str,
+    // стоимость найденного пути, None если путь не найден
+    pub cost: Option<f64>,
+    pub expanded: usize,
+    pub pushed: usize,
+    // оценка памяти очереди и найденных клеток в байтах
+    pub memory: usize,
+    pub time: Duration,
+    // отношение стоимости к стоимости пути алгоритма Дейкстры
+    pub suboptimality: Option<f64>,
+}
+
+// генератор псевдослучайных чисел xorshift64*
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15 | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// карты каталога в порядке имён файлов, файлы других форматов пропускаются
+pub fn load_maps(dir: &Path) -> Result<Vec<(String, Map)>, Box<dyn Error>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    let mut maps = Vec::new();
+    for path in paths {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let known = [MapFormat::Text, MapFormat::Json, MapFormat::Bincode]
+            .iter()
+            .any(|f| extension.as_deref() == Some(f.extension()));
+        if !path.is_file() || !known {
+            continue;
+        }
+        let map = format::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        maps.push((name, map));
+    }
+    Ok(maps)
+}
+
+// случайные пары различных свободных клеток карты
+fn random_pairs(map: &Map, count: usize, rng: &mut Rng) -> Vec<(MapPos, MapPos)> {
+    let mut cells = Vec::new();
+    for x in 0..map.rows() {
+        for y in 0..map.cols() {
+            if map[x][y] == Cell::Passable {
+                cells.push(MapPos::new(x, y));
+            }
+        }
+    }
+    if cells.len() < 2 {
+        return Vec::new();
+    }
+    (0..count)
+        .map(|_| {
+            let start = cells[rng.below(cells.len())];
+            let mut goal = cells[rng.below(cells.len() - 1)];
+            if goal == start {
+                goal = cells[cells.len() - 1];
+            }
+            (start, goal)
+        })
+        .collect()
+}
+
+// копия карты с заданной начальной и единственной конечной клеткой,
+// None если клетки вне карты или заняты
+fn with_pair(map: &Map, start: MapPos, goal: MapPos) -> Option<Map> {
+    let inside = |p: MapPos| p.x < map.rows() && p.y < map.cols();
+    if !inside(start) || !inside(goal) || start == goal {
+        return None;
+    }
+    let mut map = map.clone();
+    map.clear_path();
+    if map.start() != start {
+        if map[start.x][start.y] != Cell::Passable {
+            return None;
+        }
+        map.set_cell(Cell::Start, start);
+    }
+    match map[goal.x][goal.y] {
+        Cell::Passable => map.set_cell(Cell::Finish, goal),
+        Cell::Finish => (),
+        _ => return None,
+    }
+    for finish in map.finishes().to_vec() {
+        if finish != goal {
+            map.remove_finish(finish);
+        }
+    }
+    Some(map)
+}
+
+// запуск всех алгоритмов реестра на каждой паре клеток каждой карты
+pub fn run(maps: &[(String, Map)], pairs: &Pairs, registry: &Registry) -> Vec<BenchRecord> {
+    let mut records = Vec::new();
+    for (name, map) in maps {
+        let pairs = match pairs {
+            Pairs::Random { count, seed } => random_pairs(map, *count, &mut Rng::new(*seed)),
+            Pairs::Fixed(pairs) => pairs.clone(),
+        };
+        for (start, goal) in pairs {
+            let map = match with_pair(map, start, goal) {
+                Some(map) => map,
+                None => continue,
+            };
+            // эталонная стоимость пути
+            let optimal =
+                match ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra())).run() {
+                    SearchStatus::Found(cost) => Some(cost),
+                    _ => None,
+                };
+            for entry in registry.entries() {
+                let mut search = ShortestPath::new(map.clone(), entry.create());
+                let time = Instant::now();
+                let status = search.run();
+                let time = time.elapsed();
+                let stats = search.stats();
+                let cost = match status {
+                    SearchStatus::Found(cost) => Some(cost),
+                    _ => None,
+                };
+                let memory = stats.max_frontier * mem::size_of::<PosState>()
+                    + stats.visited * (mem::size_of::<MapPos>() + mem::size_of::<PosState>());
+                records.push(BenchRecord {
+                    map: name.clone(),
+                    start,
+                    goal,
+                    algorithm: entry.name,
+                    cost,
+                    expanded: stats.expanded,
+                    pushed: stats.pushed,
+                    memory,
+                    time,
+                    suboptimality: match (cost, optimal) {
+                        (Some(cost), Some(optimal)) if optimal > 0f64 => Some(cost / optimal),
+                        _ => None,
+                    },
+                });
+            }
+        }
+    }
+    records
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.4}", v)).unwrap_or_default()
+}
+
+// экранирование имени карты в ячейке CSV
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn millis(time: Duration) -> f64 {
+    time.as_secs() as f64 * 1000f64 + f64::from(time.subsec_nanos()) / 1_000_000f64
+}
+
+pub fn write_csv<W: Write>(records: &[BenchRecord], mut writer: W) -> Result<(), Box<dyn Error>> {
+    writeln!(
+        writer,
+        "map,start_x,start_y,goal_x,goal_y,algorithm,cost,expanded,pushed,memory_bytes,time_ms,suboptimality"
+    )?;
+    for r in records {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{:.3},{}",
+            csv_field(&r.map),
+            r.start.x,
+            r.start.y,
+            r.goal.x,
+            r.goal.y,
+            r.algorithm,
+            optional(r.cost),
+            r.expanded,
+            r.pushed,
+            r.memory,
+            millis(r.time),
+            optional(r.suboptimality)
+        )?;
+    }
+    Ok(())
+}
+
+// средние значения по алгоритму для сводной таблицы
+fn summary(records: &[BenchRecord], algorithm: &str) -> (usize, usize, f64, f64, f64, f64) {
+    let records = records
+        .iter()
+        .filter(|r| r.algorithm == algorithm)
+        .collect::<Vec<_>>();
+    let n = records.len().max(1) as f64;
+    let found = records.iter().filter(|r| r.cost.is_some()).count();
+    let ratios = records
+        .iter()
+        .filter_map(|r| r.suboptimality)
+        .collect::<Vec<_>>();
+    (
+        records.len(),
+        found,
+        records.iter().map(|r| r.expanded as f64).sum::<f64>() / n,
+        records.iter().map(|r| r.memory as f64).sum::<f64>() / n,
+        records.iter().map(|r| millis(r.time)).sum::<f64>() / n,
+        ratios.iter().sum::<f64>() / ratios.len().max(1) as f64,
+    )
+}
+
+pub fn write_html<W: Write>(records: &[BenchRecord], mut writer: W) -> Result<(), Box<dyn Error>> {
+    let mut algorithms: Vec<&str> = Vec::new();
+    for r in records {
+        if !algorithms.contains(&r.algorithm) {
+            algorithms.push(r.algorithm);
+        }
+    }
+    writeln!(
+        writer,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )?;
+    writeln!(writer, "<title>Сравнение алгоритмов поиска пути</title>")?;
+    writeln!(
+        writer,
+        "<style>table {{ border-collapse: collapse; }} \
+         td, th {{ border: 1px solid #999; padding: 2px 6px; text-align: right; }}</style>"
+    )?;
+    writeln!(writer, "</head>\n<body>\n<h1>Сводка</h1>\n<table>")?;
+    writeln!(
+        writer,
+        "<tr><th>Алгоритм</th><th>Запусков</th><th>Найдено</th><th>Раскрыто</th>\
+         <th>Память, байт</th><th>Время, мс</th><th>Субоптимальность</th></tr>"
+    )?;
+    for algorithm in &algorithms {
+        let (runs, found, expanded, memory, time, ratio) = summary(records, algorithm);
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.0}</td>\
+             <td>{:.3}</td><td>{:.4}</td></tr>",
+            algorithm, runs, found, expanded, memory, time, ratio
+        )?;
+    }
+    writeln!(writer, "</table>\n<h1>Запуски</h1>\n<table>")?;
+    writeln!(
+        writer,
+        "<tr><th>Карта</th><th>Начало</th><th>Цель</th><th>Алгоритм</th><th>Стоимость</th>\
+         <th>Раскрыто</th><th>В очереди</th><th>Память, байт</th><th>Время, мс</th>\
+         <th>Субоптимальность</th></tr>"
+    )?;
+    for r in records {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{} {}</td><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td><td>{:.3}</td><td>{}</td></tr>",
+            html_escape(&r.map),
+            r.start.x,
+            r.start.y,
+            r.goal.x,
+            r.goal.y,
+            r.algorithm,
+            optional(r.cost),
+            r.expanded,
+            r.pushed,
+            r.memory,
+            millis(r.time),
+            optional(r.suboptimality)
+        )?;
+    }
+    writeln!(writer, "</table>\n</body>\n</html>")?;
+    Ok(())
+}
+
+// запись отчёта, HTML для файлов .html и .htm, иначе CSV
+pub fn save(path: &Path, records: &[BenchRecord]) -> Result<(), Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let mut writer = BufWriter::new(File::create(path)?);
+    match extension.as_deref() {
+        Some("html") | Some("htm") => write_html(records, &mut writer)?,
+        _ => write_csv(records, &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn pos(x: usize, y: usize) -> MapPos {
+        MapPos::new(x, y)
+    }
+
+    // каталог с картами разных форматов и файлами, которые нужно пропустить
+    fn maps_dir() -> Result<Vec<(String, Map)>, Box<dyn Error>> {
+        let dir = env::temp_dir().join(format!("bench-{}", process::id()));
+        fs::create_dir_all(dir.join("c.txt"))?;
+        fs::write(dir.join("notes.md"), "S.F")?;
+        format::save(&dir.join("b.json"), &format::from_text("S.#F\n..#.\n")?)?;
+        format::save(
+            &dir.join("a.txt"),
+            &format::from_text("S...\n.##.\n...F\n")?,
+        )?;
+        let maps = load_maps(&dir);
+        fs::write(dir.join("z.txt"), "S?F\n")?;
+        let broken = load_maps(&dir);
+        fs::remove_dir_all(&dir)?;
+        assert!(broken.is_err());
+        maps
+    }
+
+    #[test]
+    fn runs_every_algorithm_on_every_pair() {
+        let maps = maps_dir().unwrap();
+        let names = maps
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a.txt", "b.json"]);
+
+        let registry = Registry::default();
+        let algorithms = registry.entries().len();
+        // пары вне карты и пары со стеной пропускаются
+        let pairs = Pairs::Fixed(vec![
+            (pos(0, 0), pos(2, 3)),
+            (pos(0, 0), pos(0, 3)),
+            (pos(0, 1), pos(1, 1)),
+            (pos(0, 0), pos(5, 5)),
+        ]);
+        let records = run(&maps, &pairs, &registry);
+        assert_eq!(records.len(), 4 * algorithms);
+        for group in records.chunks(algorithms) {
+            let reachable = !(group[0].map == "b.json" && group[0].goal == pos(0, 3));
+            for r in group {
+                assert_eq!(
+                    (&r.map, r.start, r.goal),
+                    (&group[0].map, group[0].start, group[0].goal)
+                );
+                assert_eq!(r.cost.is_some(), reachable);
+                assert_eq!(r.suboptimality.is_some(), reachable);
+                assert!(r.suboptimality.iter().all(|s| *s >= 1f64));
+                if r.algorithm == "dijkstra" {
+                    assert_eq!(r.suboptimality, r.cost.map(|_| 1f64));
+                }
+            }
+        }
+
+        let mut csv = Vec::new();
+        write_csv(&records, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap().lines().count(),
+            records.len() + 1
+        );
+    }
+
+    #[test]
+    fn random_pairs_repeat_with_seed() {
+        let map = format::from_text("S...\n.##.\n...F\n").unwrap();
+        let maps = vec![("a".to_string(), map)];
+        let registry = Registry::default();
+        let pairs = Pairs::Random { count: 5, seed: 7 };
+        let first = run(&maps, &pairs, &registry);
+        let second = run(&maps, &pairs, &registry);
+        assert_eq!(first.len(), 5 * registry.entries().len());
+        let key = |r: &BenchRecord| (r.start, r.goal, r.algorithm, r.cost, r.expanded);
+        assert_eq!(
+            first.iter().map(key).collect::<Vec<_>>(),
+            second.iter().map(key).collect::<Vec<_>>()
+        );
+        assert!(first.iter().all(|r| r.start != r.goal && r.cost.is_some()));
+    }
+}
@@ -0,0 +1,306 @@
+use animation::{self, AnimationOptions};
+use bench::{self, Pairs};
+use flow::FlowField;
+use format;
+use map::{MapPos, SearchStatus, ShortestPath};
+use render::{Renderer, EXPORT_CELL_SIZE};
+use route;
+use search::{Registry, SearchAlgorithm, SearchBudget, DEFAULT_ALGORITHM};
+use smooth::{self, Smoothing};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use trace;
+
+const USAGE: &str = "Использование:
+    shortest_path                                запуск графического интерфейса
+    shortest_path export <карта> <изображение>   экспорт карты и пути в PNG или SVG
+        --algorithm <имя>                        алгоритм поиска пути (astar)
+        --cell-size <пиксели>                    размер клетки (20)
+        --optimize                               оптимизировать порядок точек маршрута
+        --flow                                   нарисовать поле направлений к целям
+        --smooth <raw|reduce|pull|chaikin>       сглаживание пути (raw)
+        --max-nodes <число>                      предел раскрытых клеток
+        --time-limit <мс>                        предел времени поиска
+    shortest_path animate <карта> <файл.gif|каталог>
+                                                 запись анимации поиска в GIF или PNG
+        --algorithm <имя>                        алгоритм поиска пути (astar)
+        --steps <число>                          итераций поиска на кадр (1)
+        --delay <мс>                             задержка между кадрами (50)
+        --cell-size <пиксели>                    размер клетки (20)
+        --max-nodes <число>                      предел раскрытых клеток
+        --time-limit <мс>                        предел времени поиска
+    shortest_path trace <карта> <трасса.jsonl>  запись трассы поиска в JSON Lines
+        --algorithm <имя>                        алгоритм поиска пути (astar)
+        --max-nodes <число>                      предел раскрытых клеток
+        --time-limit <мс>                        предел времени поиска
+    shortest_path bench <каталог> <отчёт.csv|html>
+                                                 сравнение алгоритмов на картах каталога
+        --pairs <число>                          случайных пар клеток на карту (10)
+        --seed <число>                           начальное значение случайных пар (1)
+        --points <файл>                          пары клеток из файла, строки \"x y x y\"";
+
+// параметры командной строки, не принимающие значения
+const FLAGS: &[&str] = &["optimize", "flow"];
+
+// аргументы командной строки: позиционные, именованные вида --имя значение
+// и флаги без значения
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut flags = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                if FLAGS.contains(&name) {
+                    flags.push(name.to_string());
+                    continue;
+                }
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("Не указано значение параметра {}", arg))?;
+                options.insert(name.to_string(), value.clone());
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+            flags,
+        })
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.positional
+            .get(index)
+            .map(|s| s.as_str())
+            .ok_or_else(|| format!("Не указан аргумент <{}>\n\n{}", name, USAGE).into())
+    }
+
+    fn algorithm_name(&self) -> &str {
+        self.options
+            .get("algorithm")
+            .map_or(DEFAULT_ALGORITHM, |s| s.as_str())
+    }
+
+    // алгоритм поиска из реестра по имени
+    fn algorithm(&self, registry: &Registry) -> Result<Box<dyn SearchAlgorithm>, Box<dyn Error>> {
+        let name = self.algorithm_name();
+        registry.create(name).ok_or_else(|| {
+            format!("Неизвестный алгоритм: {}\n\n{}", name, algorithms(registry)).into()
+        })
+    }
+
+    // флаг без значения
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn smoothing(&self) -> Result<Smoothing, Box<dyn Error>> {
+        match self.options.get("smooth") {
+            Some(name) => Smoothing::from_name(name)
+                .ok_or_else(|| format!("Неизвестный способ сглаживания: {}", name).into()),
+            None => Ok(Smoothing::Raw),
+        }
+    }
+
+    fn number(&self, name: &str, default: usize) -> Result<usize, Box<dyn Error>> {
+        Ok(self.optional_number(name)?.unwrap_or(default))
+    }
+
+    // числовой параметр без значения по умолчанию
+    fn optional_number(&self, name: &str) -> Result<Option<usize>, Box<dyn Error>> {
+        match self.options.get(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Параметр --{} должен быть числом", name).into()),
+            None => Ok(None),
+        }
+    }
+
+    // ограничения поиска из параметров --max-nodes и --time-limit
+    fn budget(&self) -> Result<SearchBudget, Box<dyn Error>> {
+        Ok(SearchBudget {
+            max_nodes: self.optional_number("max-nodes")?,
+            time_limit: self
+                .optional_number("time-limit")?
+                .map(|ms| Duration::from_millis(ms as u64)),
+            cancel: None,
+        })
+    }
+}
+
+// список алгоритмов реестра для справки
+fn algorithms(registry: &Registry) -> String {
+    let mut text = String::from("Алгоритмы:");
+    for entry in registry.entries() {
+        text.push_str(&format!("\n    {:<12} {}", entry.name, entry.title));
+    }
+    text
+}
+
+// выполнение команды, переданной в командной строке,
+// алгоритмы поиска выбираются из реестра
+pub fn run(args: &[String], registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let command = args[0].as_str();
+    let args = Args::parse(&args[1..])?;
+    match command {
+        "export" => export(&args, registry),
+        "animate" => animate(&args, registry),
+        "trace" => record_trace(&args, registry),
+        "bench" => benchmark(&args, registry),
+        "help" | "--help" | "-h" => {
+            println!("{}\n\n{}", USAGE, algorithms(registry));
+            Ok(())
+        }
+        _ => Err(format!("Неизвестная команда: {}\n\n{}", command, USAGE).into()),
+    }
+}
+
+// поиск пути на карте и экспорт результата в изображение,
+// на карте с промежуточными клетками строится маршрут через них
+fn export(args: &Args, registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let mut map = format::load(Path::new(args.positional(0, "карта")?))?;
+    let image = Path::new(args.positional(1, "изображение")?);
+    let cell_size = args.number("cell-size", EXPORT_CELL_SIZE)?;
+    let flow = if args.flag("flow") {
+        Some(FlowField::new(&map))
+    } else {
+        None
+    };
+    if !map.waypoints().is_empty() {
+        let route = if args.flag("optimize") {
+            route::optimize(&map)
+        } else {
+            route::plan(&map)
+        };
+        match route {
+            Some(ref route) => {
+                map.set_waypoints(route.order.clone());
+                println!("Длина маршрута: {:.2}", route.length());
+            }
+            None => println!("Маршрут не найден"),
+        }
+        Renderer::new(&map)
+            .with_route(route.as_ref())
+            .with_flow(flow.as_ref())
+            .export(image, cell_size)?;
+        return Ok(());
+    }
+    let mut search = ShortestPath::new(map, args.algorithm(registry)?);
+    search.set_budget(args.budget()?);
+    let mut partial = None;
+    match search.run() {
+        SearchStatus::Found(len) => println!("Длина пути: {:.2}", len),
+        SearchStatus::BudgetExhausted { path, cost } => {
+            println!("Бюджет поиска исчерпан, длина частичного пути: {:.2}", cost);
+            partial = Some(path);
+        }
+        _ => println!("Путь не найден"),
+    }
+    let smoothing = args.smoothing()?;
+    let path = search.path().or(partial);
+    let curves = match (smoothing, &path) {
+        (Smoothing::Raw, _) | (_, None) => Vec::new(),
+        (_, Some(path)) => {
+            let curve = smooth::smooth(&search.map, path, smoothing);
+            println!("Длина после сглаживания: {:.2}", smooth::length(&curve));
+            vec![curve]
+        }
+    };
+    let path = if curves.is_empty() { path } else { None };
+    Renderer::new(&search.map)
+        .with_path(path.as_deref())
+        .with_curves(&curves)
+        .with_flow(flow.as_ref())
+        .export(image, cell_size)?;
+    Ok(())
+}
+
+// запись анимации поиска пути
+fn animate(args: &Args, registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let map = format::load(Path::new(args.positional(0, "карта")?))?;
+    let output = Path::new(args.positional(1, "файл.gif|каталог")?);
+    let defaults = AnimationOptions::default();
+    let options = AnimationOptions {
+        steps_per_frame: args.number("steps", defaults.steps_per_frame)?,
+        delay: u16::try_from(args.number("delay", usize::from(defaults.delay))?)
+            .map_err(|_| format!("Параметр --delay должен быть не больше {}", u16::MAX))?,
+        cell_size: args.number("cell-size", defaults.cell_size)?,
+    };
+    let mut search = ShortestPath::new(map, args.algorithm(registry)?);
+    search.set_budget(args.budget()?);
+    let frames = animation::export(&mut search, output, &options)?;
+    println!("Записано кадров: {}", frames);
+    Ok(())
+}
+
+// поиск пути с записью трассы для последующего воспроизведения
+fn record_trace(args: &Args, registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let map = format::load(Path::new(args.positional(0, "карта")?))?;
+    let output = Path::new(args.positional(1, "трасса.jsonl")?);
+    let mut search = ShortestPath::new(map, args.algorithm(registry)?);
+    search.set_budget(args.budget()?);
+    match trace::record(&mut search, args.algorithm_name(), output)? {
+        SearchStatus::Found(len) => println!("Длина пути: {:.2}", len),
+        SearchStatus::BudgetExhausted { cost, .. } => {
+            println!("Бюджет поиска исчерпан, длина частичного пути: {:.2}", cost)
+        }
+        _ => println!("Путь не найден"),
+    }
+    println!("Раскрыто клеток: {}", search.stats().expanded);
+    Ok(())
+}
+
+// пары клеток из файла: строки из четырёх чисел, пустые строки и строки,
+// начинающиеся с #, пропускаются
+fn read_pairs(path: &Path) -> Result<Vec<(MapPos, MapPos)>, Box<dyn Error>> {
+    let mut pairs = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|n| n.len() == 4)
+            .ok_or_else(|| format!("Строка {}: ожидалось четыре числа", i + 1))?;
+        pairs.push((
+            MapPos::new(numbers[0], numbers[1]),
+            MapPos::new(numbers[2], numbers[3]),
+        ));
+    }
+    Ok(pairs)
+}
+
+// сравнение всех алгоритмов реестра на картах каталога
+fn benchmark(args: &Args, registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let maps = bench::load_maps(Path::new(args.positional(0, "каталог")?))?;
+    let report = Path::new(args.positional(1, "отчёт.csv|html")?);
+    let pairs = match args.options.get("points") {
+        Some(path) => Pairs::Fixed(read_pairs(Path::new(path))?),
+        None => Pairs::Random {
+            count: args.number("pairs", 10)?,
+            seed: args.number("seed", 1)? as u64,
+        },
+    };
+    let records = bench::run(&maps, &pairs, registry);
+    bench::save(report, &records)?;
+    println!("Карт: {}, запусков: {}", maps.len(), records.len());
+    Ok(())
+}
@@ -0,0 +1,148 @@
+// цвет в пространстве RGB, компоненты в диапазоне от 0 до 1
+#[derive(Debug, Clone)]
+pub struct Color {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+impl Color {
+    pub fn from_rgb8(red: u8, green: u8, blue: u8) -> Self {
+        Self {
+            red: f64::from(red) / 255f64,
+            green: f64::from(green) / 255f64,
+            blue: f64::from(blue) / 255f64,
+        }
+    }
+
+    pub fn black() -> Self {
+        Self {
+            red: 0f64,
+            green: 0f64,
+            blue: 0f64,
+        }
+    }
+
+    pub fn white() -> Self {
+        Self {
+            red: 1f64,
+            green: 1f64,
+            blue: 1f64,
+        }
+    }
+
+    pub fn red() -> Self {
+        Self {
+            red: 1f64,
+            green: 0f64,
+            blue: 0f64,
+        }
+    }
+
+    pub fn green() -> Self {
+        Self {
+            red: 0f64,
+            green: 1f64,
+            blue: 0f64,
+        }
+    }
+
+    pub fn grey() -> Self {
+        Self {
+            red: 0.5f64,
+            green: 0.5f64,
+            blue: 0.5f64,
+        }
+    }
+
+    pub fn yellow() -> Self {
+        Self {
+            red: 1f64,
+            green: 1f64,
+            blue: 0f64,
+        }
+    }
+
+    pub fn orange() -> Self {
+        Self {
+            red: 1f64,
+            green: 0.546875f64,
+            blue: 0f64,
+        }
+    }
+
+    pub fn cyan() -> Self {
+        Self {
+            red: 0f64,
+            green: 1f64,
+            blue: 1f64,
+        }
+    }
+
+    pub fn magenta() -> Self {
+        Self {
+            red: 1f64,
+            green: 0f64,
+            blue: 1f64,
+        }
+    }
+
+    pub fn pale_blue() -> Self {
+        Self {
+            red: 0.68359375f64,
+            green: 0.9296875f64,
+            blue: 0.9296875f64,
+        }
+    }
+
+    pub fn pale_green() -> Self {
+        Self {
+            red: 0.59375f64,
+            green: 0.98046875f64,
+            blue: 0.59375f64,
+        }
+    }
+
+    pub fn brown() -> Self {
+        Self {
+            red: 0.546875f64,
+            green: 0.26953125f64,
+            blue: 0.07421875f64,
+        }
+    }
+
+    // насыщенный цвет с оттенком в диапазоне от 0 до 1
+    pub fn from_hue(hue: f64) -> Self {
+        let channel = |shift: f64| {
+            let k = (hue.fract() * 6f64 + shift) % 6f64;
+            1f64 - (k.min(4f64 - k).clamp(0f64, 1f64))
+        };
+        Self {
+            red: channel(5f64),
+            green: channel(3f64),
+            blue: channel(1f64),
+        }
+    }
+
+    // яркость цвета в диапазоне от 0 до 1
+    pub fn brightness(&self) -> f64 {
+        0.299f64 * self.red + 0.587f64 * self.green + 0.114f64 * self.blue
+    }
+
+    // евклидово расстояние между цветами в пространстве RGB
+    pub fn distance(&self, other: &Color) -> f64 {
+        ((self.red - other.red).powi(2)
+            + (self.green - other.green).powi(2)
+            + (self.blue - other.blue).powi(2))
+        .sqrt()
+    }
+
+    // смешивание цветов, t = 0 даёт исходный цвет, t = 1 - другой
+    pub fn mix(&self, other: &Color, t: f64) -> Self {
+        Self {
+            red: self.red + (other.red - self.red) * t,
+            green: self.green + (other.green - self.green) * t,
+            blue: self.blue + (other.blue - self.blue) * t,
+        }
+    }
+}
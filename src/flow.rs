@@ -0,0 +1,232 @@
+use map::{Map, MapPos, PosState};
+use std::collections::BinaryHeap;
+use std::f64;
+
+// поле направлений: расстояние от каждой клетки до ближайшей цели
+// и следующая клетка на кратчайшем пути к ней; поле от источников
+// хранит те же данные для путей из источников, записанных от конца к началу
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    cols: usize,
+    distance: Vec<f64>,
+    next: Vec<Option<MapPos>>,
+}
+
+impl FlowField {
+    // поле к конечным клеткам карты
+    pub fn new(map: &Map) -> Self {
+        FlowField::from_goals(map, map.finishes())
+    }
+
+    // обратный алгоритм Дейкстры от целей по всей карте
+    pub fn from_goals(map: &Map, goals: &[MapPos]) -> Self {
+        FlowField::build(map, goals, true)
+    }
+
+    // алгоритм Дейкстры от источников по всей карте: расстояние - длина пути
+    // из ближайшего источника, следующая клетка - предыдущая на этом пути;
+    // переходы могут быть несимметричны, поэтому это поле отличается от поля к целям
+    pub fn from_sources(map: &Map, sources: &[MapPos]) -> Self {
+        FlowField::build(map, sources, false)
+    }
+
+    fn build(map: &Map, roots: &[MapPos], reverse: bool) -> Self {
+        let cols = map.cols();
+        let len = map.rows() * cols;
+        let mut field = FlowField {
+            cols,
+            distance: vec![f64::INFINITY; len],
+            next: vec![None; len],
+        };
+        let mut queue = BinaryHeap::new();
+        for root in roots {
+            let i = field.index(*root);
+            field.distance[i] = 0f64;
+            queue.push(PosState {
+                pos: *root,
+                cost: 0f64,
+            });
+        }
+        while let Some(state) = queue.pop() {
+            if state.cost > field.distance(state.pos) {
+                continue;
+            }
+            // переход из соседней клетки в текущую или из текущей в соседнюю
+            let adjacent = if reverse {
+                map.predecessors(state.pos)
+            } else {
+                map.neighbors(state.pos)
+            };
+            for pos in adjacent {
+                let step = if reverse {
+                    map.cost(pos, state.pos)
+                } else {
+                    map.cost(state.pos, pos)
+                };
+                let cost = state.cost + step;
+                let i = field.index(pos);
+                if cost < field.distance[i] {
+                    field.distance[i] = cost;
+                    field.next[i] = Some(state.pos);
+                    queue.push(PosState { pos, cost });
+                }
+            }
+        }
+        field
+    }
+
+    fn index(&self, pos: MapPos) -> usize {
+        pos.x * self.cols + pos.y
+    }
+
+    // расстояние до ближайшей цели, бесконечность для недостижимых клеток
+    pub fn distance(&self, pos: MapPos) -> f64 {
+        self.distance[self.index(pos)]
+    }
+
+    // следующая клетка на пути к цели, None для целей и недостижимых клеток
+    pub fn next(&self, pos: MapPos) -> Option<MapPos> {
+        self.next[self.index(pos)]
+    }
+
+    // направление перехода к следующей клетке (по строкам, по столбцам),
+    // на замкнутой карте переход через край - шаг в сторону края
+    pub fn direction(&self, pos: MapPos) -> Option<(isize, isize)> {
+        let rows = self.distance.len() / self.cols;
+        // соседние клетки отличаются не больше чем на 1 в каждой координате
+        let step = |d: isize, len: usize| match d {
+            d if d > 1 => d - len as isize,
+            d if d < -1 => d + len as isize,
+            d => d,
+        };
+        self.next(pos).map(|next| {
+            (
+                step(next.x as isize - pos.x as isize, rows),
+                step(next.y as isize - pos.y as isize, self.cols),
+            )
+        })
+    }
+
+    // путь от клетки до ближайшей цели
+    pub fn path(&self, from: MapPos) -> Option<Vec<MapPos>> {
+        if !self.distance(from).is_finite() {
+            return None;
+        }
+        let mut path = vec![from];
+        let mut pos = from;
+        while let Some(next) = self.next(pos) {
+            path.push(next);
+            pos = next;
+        }
+        Some(path)
+    }
+
+    // наибольшее конечное расстояние, используется для градиента
+    pub fn max_distance(&self) -> f64 {
+        self.distance
+            .iter()
+            .cloned()
+            .filter(|d| d.is_finite())
+            .fold(0f64, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{Cell, Direction, Passage, PassageKind, SearchStatus, ShortestPath};
+    use search::BestFirst;
+
+    fn cost(map: &Map) -> Option<f64> {
+        match ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra())).run() {
+            SearchStatus::Found(cost) => Some(cost),
+            _ => None,
+        }
+    }
+
+    // карта с весами, конвейером, склоном и замкнутой клеткой в правом верхнем углу
+    fn map() -> Map {
+        let mut map =
+            format::from_text("S..#..#.\n.3.#.###\n.#..5#..\n...#...F\n#..#.#..\n").unwrap();
+        let conveyor = Passage::new(PassageKind::Conveyor, Direction::E);
+        let slope = Passage::new(PassageKind::Slope, Direction::N);
+        map.set_passage(MapPos::new(2, 3), Some(conveyor)).unwrap();
+        map.set_passage(MapPos::new(3, 2), Some(slope)).unwrap();
+        map
+    }
+
+    // совпадение расстояний, в том числе бесконечных
+    fn same(a: f64, b: f64) -> bool {
+        a == b || (a - b).abs() < 1e-9
+    }
+
+    fn free_cells(map: &Map) -> Vec<MapPos> {
+        let mut cells = Vec::new();
+        for x in 0..map.rows() {
+            for y in 0..map.cols() {
+                if map[x][y] == Cell::Passable {
+                    cells.push(MapPos::new(x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn distances_match_dijkstra() {
+        let map = map();
+        let to_goals = FlowField::new(&map);
+        let from_start = FlowField::from_sources(&map, &[map.start()]);
+        for pos in free_cells(&map) {
+            let mut from = map.clone();
+            from.set_cell(Cell::Start, pos);
+            let expected = cost(&from).unwrap_or(f64::INFINITY);
+            assert!(same(to_goals.distance(pos), expected));
+            let mut to = map.clone();
+            assert!(to.move_finish(map.finishes()[0], pos));
+            let expected = cost(&to).unwrap_or(f64::INFINITY);
+            assert!(same(from_start.distance(pos), expected));
+        }
+        // склон и конвейер делают путь к цели и обратно разными
+        let goal = map.finishes()[0];
+        assert!(free_cells(&map).into_iter().any(|p| {
+            let back = FlowField::from_goals(&map, &[p]).distance(goal);
+            !same(to_goals.distance(p), back)
+        }));
+    }
+
+    #[test]
+    fn paths_follow_the_field() {
+        let map = map();
+        let field = FlowField::new(&map);
+        for pos in free_cells(&map).into_iter().chain(Some(map.start())) {
+            let distance = field.distance(pos);
+            let path = match field.path(pos) {
+                Some(path) => path,
+                None => {
+                    assert!(!distance.is_finite());
+                    assert_eq!(field.next(pos), None);
+                    continue;
+                }
+            };
+            assert_eq!(path[0], pos);
+            assert!(map.finishes().contains(&path[path.len() - 1]));
+            let mut total = 0f64;
+            for w in path.windows(2) {
+                assert!(map.neighbors(w[0]).any(|p| p == w[1]));
+                total += map.cost(w[0], w[1]);
+            }
+            assert!((total - distance).abs() < 1e-9);
+        }
+        // замкнутая клетка и стены недостижимы
+        for pos in &[MapPos::new(0, 7), MapPos::new(0, 3)] {
+            assert!(!field.distance(*pos).is_finite());
+            assert_eq!(field.next(*pos), None);
+            assert_eq!(field.path(*pos), None);
+        }
+        let goal = map.finishes()[0];
+        assert_eq!(field.path(goal), Some(vec![goal]));
+        assert_eq!(field.next(goal), None);
+    }
+}
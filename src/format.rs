@@ -0,0 +1,846 @@
+use bincode;
+use map::{
+    Agent, Cell, Direction, Map, MapError, MapPos, MapV1, MapV2, MapV3, MapV4, MapV5, MapV6, MapV7,
+    Passage, PassageKind, Topology, MAX_CELLS, MAX_WEIGHT,
+};
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// формат файла карты
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    Text,
+    Json,
+    Bincode,
+}
+
+impl MapFormat {
+    // все поддерживаемые форматы в порядке отображения в диалоге выбора файла
+    pub const ALL: [MapFormat; 3] = [MapFormat::Text, MapFormat::Json, MapFormat::Bincode];
+
+    // формат определяется по расширению файла,
+    // файлы с неизвестным расширением считаются картами в старом формате bincode
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("txt") => MapFormat::Text,
+            Some("json") => MapFormat::Json,
+            _ => MapFormat::Bincode,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MapFormat::ALL.iter().cloned().find(|f| f.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MapFormat::Text => "Текстовая карта",
+            MapFormat::Json => "Карта JSON",
+            MapFormat::Bincode => "Карта bincode",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MapFormat::Text => "txt",
+            MapFormat::Json => "json",
+            MapFormat::Bincode => "bin",
+        }
+    }
+}
+
+// ошибка чтения или записи карты
+#[derive(Debug)]
+pub enum FormatError {
+    Io(io::Error),
+    Bincode(bincode::Error),
+    Json(serde_json::Error),
+    Text(String),
+    Invalid(MapError),
+    Version(u16),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "{}", err),
+            FormatError::Bincode(err) => write!(f, "{}", err),
+            FormatError::Json(err) => write!(f, "{}", err),
+            FormatError::Text(message) => write!(f, "{}", message),
+            FormatError::Invalid(err) => write!(f, "Некорректная карта: {}", err),
+            FormatError::Version(version) => write!(
+                f,
+                "Неподдерживаемая версия файла карты: {} (последняя {})",
+                version, VERSION
+            ),
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+impl From<io::Error> for FormatError {
+    fn from(err: io::Error) -> Self {
+        FormatError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for FormatError {
+    fn from(err: bincode::Error) -> Self {
+        FormatError::Bincode(err)
+    }
+}
+
+impl From<serde_json::Error> for FormatError {
+    fn from(err: serde_json::Error) -> Self {
+        FormatError::Json(err)
+    }
+}
+
+impl From<MapError> for FormatError {
+    fn from(err: MapError) -> Self {
+        FormatError::Invalid(err)
+    }
+}
+
+// сигнатура двоичного файла карты
+const MAGIC: &[u8; 4] = b"SPMP";
+// текущая версия двоичного формата,
+// версия 0 - файлы без заголовка, сохранённые до появления версий,
+// версия 2 - добавлены веса клеток,
+// версия 3 - несколько конечных клеток,
+// версия 4 - промежуточные клетки маршрута,
+// версия 5 - агенты,
+// версия 6 - форма клеток,
+// версия 7 - соединение краёв,
+// версия 8 - направленные клетки
+pub const VERSION: u16 = 8;
+
+// наибольший объём данных, читаемых bincode из файла карты: клетка, вес и
+// направление клетки, её позиция в списке целей или промежуточных клеток
+// и половина агента занимают меньше 64 байт, поэтому повреждённые длины
+// в файле не заставят прочитать больше, чем занимает карта из MAX_CELLS клеток
+const MAX_BINARY_SIZE: u64 = MAX_CELLS as u64 * 64;
+
+// представление карты в JSON, клетки записываются строками текстового формата,
+// порядок посещения промежуточных клеток записывается отдельно
+#[derive(Serialize, Deserialize)]
+struct JsonMap {
+    rows: usize,
+    cols: usize,
+    cells: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    waypoints: Vec<MapPos>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    agents: Vec<Agent>,
+    #[serde(default, skip_serializing_if = "Topology::is_square")]
+    topology: Topology,
+    #[serde(default, skip_serializing_if = "is_false")]
+    wrap: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    passages: Vec<JsonPassage>,
+}
+
+// направленная клетка в JSON
+#[derive(Serialize, Deserialize)]
+struct JsonPassage {
+    pos: MapPos,
+    kind: PassageKind,
+    direction: Direction,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// агенты в текстовом формате записываются после карты строками
+// вида "@ x y x y" с начальной и целевой клетками
+const AGENT_PREFIX: char = '@';
+
+// направленные клетки в текстовом формате записываются после карты
+// строками вида "> x y conveyor ne" с видом и направлением клетки
+const PASSAGE_PREFIX: char = '>';
+
+fn passage_to_line(pos: MapPos, passage: Passage) -> String {
+    format!(
+        "{} {} {} {} {}",
+        PASSAGE_PREFIX,
+        pos.x,
+        pos.y,
+        passage.kind.name(),
+        passage.direction.name()
+    )
+}
+
+fn line_to_passage(line: &str) -> Result<(MapPos, Passage), FormatError> {
+    let error = || FormatError::Text(format!("Неверная запись направленной клетки: {}", line));
+    let words = line[PASSAGE_PREFIX.len_utf8()..]
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    match words.as_slice() {
+        [x, y, kind, direction] => {
+            let x = x.parse::<usize>().map_err(|_| error())?;
+            let y = y.parse::<usize>().map_err(|_| error())?;
+            let kind = PassageKind::from_name(kind).ok_or_else(error)?;
+            let direction = Direction::from_name(direction).ok_or_else(error)?;
+            Ok((MapPos::new(x, y), Passage::new(kind, direction)))
+        }
+        _ => Err(error()),
+    }
+}
+
+// добавление направленных клеток к карте
+fn add_passages(map: &mut Map, passages: Vec<(MapPos, Passage)>) -> Result<(), FormatError> {
+    for (pos, passage) in passages {
+        map.set_passage(pos, Some(passage))?;
+    }
+    Ok(())
+}
+
+// форма клеток, отличная от квадратной, и соединение краёв записываются
+// перед картой строкой вида "~ hex wrap"
+const TOPOLOGY_PREFIX: char = '~';
+const WRAP: &str = "wrap";
+
+fn topology_to_line(map: &Map) -> Option<String> {
+    let mut words = Vec::new();
+    if !map.topology().is_square() {
+        words.push(map.topology().name());
+    }
+    if map.wrap() {
+        words.push(WRAP);
+    }
+    if words.is_empty() {
+        None
+    } else {
+        Some(format!("{} {}", TOPOLOGY_PREFIX, words.join(" ")))
+    }
+}
+
+fn apply_topology_line(map: &mut Map, line: &str) -> Result<(), FormatError> {
+    for word in line[TOPOLOGY_PREFIX.len_utf8()..].split_whitespace() {
+        if word == WRAP {
+            map.set_wrap(true);
+        } else {
+            let topology = Topology::from_name(word)
+                .ok_or_else(|| FormatError::Text(format!("Неизвестная форма клеток: {}", word)))?;
+            map.set_topology(topology);
+        }
+    }
+    Ok(())
+}
+
+// порядок посещения промежуточных клеток в текстовом формате записывается
+// после карты строкой вида "* x y x y", символ W клетки порядка не хранит
+const WAYPOINTS_PREFIX: char = '*';
+
+fn waypoints_to_line(map: &Map) -> Option<String> {
+    if map.waypoints().is_empty() {
+        return None;
+    }
+    let mut line = WAYPOINTS_PREFIX.to_string();
+    for pos in map.waypoints() {
+        line.push_str(&format!(" {} {}", pos.x, pos.y));
+    }
+    Some(line)
+}
+
+fn line_to_waypoints(line: &str) -> Result<Vec<MapPos>, FormatError> {
+    let error = || FormatError::Text(format!("Неверная запись порядка точек маршрута: {}", line));
+    let numbers = line[WAYPOINTS_PREFIX.len_utf8()..]
+        .split_whitespace()
+        .map(|n| n.parse::<usize>().map_err(|_| error()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() % 2 != 0 {
+        return Err(error());
+    }
+    Ok(numbers.chunks(2).map(|p| MapPos::new(p[0], p[1])).collect())
+}
+
+// порядок посещения промежуточных клеток, без явного порядка
+// клетки посещаются в порядке обхода по строкам
+fn set_waypoints(map: &mut Map, waypoints: Vec<MapPos>) -> Result<(), FormatError> {
+    if !waypoints.is_empty() && !map.set_waypoints(waypoints) {
+        return Err(FormatError::Text(
+            "Порядок точек маршрута не совпадает с промежуточными клетками карты".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn agent_to_line(agent: &Agent) -> String {
+    format!(
+        "{} {} {} {} {}",
+        AGENT_PREFIX, agent.start.x, agent.start.y, agent.goal.x, agent.goal.y
+    )
+}
+
+fn line_to_agent(line: &str) -> Result<Agent, FormatError> {
+    let error = || FormatError::Text(format!("Неверная запись агента: {}", line));
+    let numbers = line[AGENT_PREFIX.len_utf8()..]
+        .split_whitespace()
+        .map(|n| n.parse::<usize>().map_err(|_| error()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match numbers.as_slice() {
+        [sx, sy, gx, gy] => Ok(Agent::new(MapPos::new(*sx, *sy), MapPos::new(*gx, *gy))),
+        _ => Err(error()),
+    }
+}
+
+// добавление агентов к карте
+fn add_agents(map: &mut Map, agents: Vec<Agent>) -> Result<(), FormatError> {
+    for agent in agents {
+        if !map.add_agent(agent) {
+            return Err(FormatError::Invalid(MapError::Agent(agent.start)));
+        }
+    }
+    Ok(())
+}
+
+// символ клетки в текстовом формате,
+// проходимые клетки с весом больше 1 записываются цифрой веса
+fn cell_to_char(cell: Cell, weight: u8) -> char {
+    match cell {
+        Cell::Impassable => '#',
+        Cell::Start => 'S',
+        Cell::Finish => 'F',
+        Cell::Waypoint => 'W',
+        Cell::Passable | Cell::Visited | Cell::InQueue => match weight {
+            0 | 1 => '.',
+            w => (b'0' + w.min(MAX_WEIGHT)) as char,
+        },
+    }
+}
+
+fn char_to_cell(c: char) -> Option<(Cell, u8)> {
+    match c {
+        '.' => Some((Cell::Passable, 1)),
+        '#' => Some((Cell::Impassable, 1)),
+        'S' => Some((Cell::Start, 1)),
+        'F' => Some((Cell::Finish, 1)),
+        'W' => Some((Cell::Waypoint, 1)),
+        '1'..='9' => Some((Cell::Passable, c as u8 - b'0')),
+        _ => None,
+    }
+}
+
+// строки карты в текстовом формате, стена по периметру не записывается
+fn to_lines(map: &Map) -> Vec<String> {
+    (0..map.rows())
+        .map(|i| {
+            map[i]
+                .iter()
+                .enumerate()
+                .map(|(j, c)| cell_to_char(*c, map.weight(MapPos::new(i, j))))
+                .collect()
+        })
+        .collect()
+}
+
+fn from_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> Result<Map, FormatError> {
+    let mut cols = 0;
+    let mut cells = Vec::new();
+    let mut weights = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let len = cells.len();
+        for (j, c) in line.chars().enumerate() {
+            match char_to_cell(c) {
+                Some((cell, weight)) => {
+                    cells.push(cell);
+                    weights.push(weight);
+                }
+                None => {
+                    return Err(FormatError::Text(format!(
+                        "Неизвестный символ '{}' в строке {}, столбце {}",
+                        c,
+                        i + 1,
+                        j + 1
+                    )))
+                }
+            }
+        }
+        if i == 0 {
+            cols = cells.len();
+        } else if cells.len() - len != cols {
+            return Err(FormatError::Text(format!(
+                "Строка {} имеет длину {}, ожидалось {}",
+                i + 1,
+                cells.len() - len,
+                cols
+            )));
+        }
+    }
+    let rows = cells.len().checked_div(cols).unwrap_or(0);
+    let mut map = Map::from_cells(rows, cols, &cells)?;
+    for (k, weight) in weights.into_iter().enumerate() {
+        map.set_weight(MapPos::new(k / cols, k % cols), weight);
+    }
+    Ok(map)
+}
+
+// преобразование карты в текстовый формат
+pub fn to_text(map: &Map) -> String {
+    let mut lines = Vec::new();
+    lines.extend(topology_to_line(map));
+    lines.extend(to_lines(map));
+    lines.extend(waypoints_to_line(map));
+    lines.extend(map.agents().iter().map(agent_to_line));
+    lines.extend(
+        map.passages()
+            .into_iter()
+            .map(|(pos, p)| passage_to_line(pos, p)),
+    );
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// чтение карты из текстового формата, пустые строки игнорируются
+pub fn from_text(text: &str) -> Result<Map, FormatError> {
+    let lines = text.lines().map(|l| l.trim_end()).filter(|l| !l.is_empty());
+    let (topology, lines): (Vec<_>, Vec<_>) = lines.partition(|l| l.starts_with(TOPOLOGY_PREFIX));
+    let (waypoints, lines): (Vec<_>, Vec<_>) = lines
+        .into_iter()
+        .partition(|l| l.starts_with(WAYPOINTS_PREFIX));
+    let (agents, lines): (Vec<_>, Vec<_>) =
+        lines.into_iter().partition(|l| l.starts_with(AGENT_PREFIX));
+    let (passages, cells): (Vec<_>, Vec<_>) = lines
+        .into_iter()
+        .partition(|l| l.starts_with(PASSAGE_PREFIX));
+    let mut map = from_lines(cells.into_iter())?;
+    for line in topology {
+        apply_topology_line(&mut map, line)?;
+    }
+    let mut order = Vec::new();
+    for line in waypoints {
+        order.extend(line_to_waypoints(line)?);
+    }
+    set_waypoints(&mut map, order)?;
+    let agents = agents
+        .into_iter()
+        .map(line_to_agent)
+        .collect::<Result<Vec<_>, _>>()?;
+    add_agents(&mut map, agents)?;
+    let passages = passages
+        .into_iter()
+        .map(line_to_passage)
+        .collect::<Result<Vec<_>, _>>()?;
+    add_passages(&mut map, passages)?;
+    Ok(map)
+}
+
+fn to_json_map(map: &Map) -> JsonMap {
+    JsonMap {
+        rows: map.rows(),
+        cols: map.cols(),
+        cells: to_lines(map),
+        waypoints: map.waypoints().to_vec(),
+        agents: map.agents().to_vec(),
+        topology: map.topology(),
+        wrap: map.wrap(),
+        passages: map
+            .passages()
+            .into_iter()
+            .map(|(pos, p)| JsonPassage {
+                pos,
+                kind: p.kind,
+                direction: p.direction,
+            })
+            .collect(),
+    }
+}
+
+fn from_json_map(json: JsonMap) -> Result<Map, FormatError> {
+    if json.cells.len() != json.rows {
+        return Err(FormatError::Text(format!(
+            "Карта содержит {} строк, ожидалось {}",
+            json.cells.len(),
+            json.rows
+        )));
+    }
+    let mut map = from_lines(json.cells.iter().map(|l| l.as_str()))?;
+    if map.cols() != json.cols {
+        return Err(FormatError::Text(format!(
+            "Карта содержит {} столбцов, ожидалось {}",
+            map.cols(),
+            json.cols
+        )));
+    }
+    set_waypoints(&mut map, json.waypoints)?;
+    add_agents(&mut map, json.agents)?;
+    map.set_topology(json.topology);
+    map.set_wrap(json.wrap);
+    let passages = json
+        .passages
+        .into_iter()
+        .map(|p| (p.pos, Passage::new(p.kind, p.direction)))
+        .collect();
+    add_passages(&mut map, passages)?;
+    Ok(map)
+}
+
+pub fn to_json(map: &Map) -> Result<String, FormatError> {
+    Ok(serde_json::to_string_pretty(&to_json_map(map))?)
+}
+
+pub fn from_json(text: &str) -> Result<Map, FormatError> {
+    from_json_map(serde_json::from_str(text)?)
+}
+
+// карта в виде значения JSON для вложения в другие документы
+pub fn to_json_value(map: &Map) -> Result<serde_json::Value, FormatError> {
+    Ok(serde_json::to_value(to_json_map(map))?)
+}
+
+pub fn from_json_value(value: serde_json::Value) -> Result<Map, FormatError> {
+    from_json_map(serde_json::from_value(value)?)
+}
+
+// двоичный формат: сигнатура, версия (u16, little endian) и карта в bincode
+pub fn to_binary(map: &Map) -> Result<Vec<u8>, FormatError> {
+    let mut vec = Vec::with_capacity(MAGIC.len() + 2);
+    vec.extend_from_slice(MAGIC);
+    vec.push(VERSION as u8);
+    vec.push((VERSION >> 8) as u8);
+    vec.extend(bincode::serialize(map)?);
+    Ok(vec)
+}
+
+pub fn from_binary(bytes: &[u8]) -> Result<Map, FormatError> {
+    let (version, payload) = if bytes.len() >= MAGIC.len() + 2 && &bytes[..MAGIC.len()] == MAGIC {
+        let version = u16::from(bytes[MAGIC.len()]) | u16::from(bytes[MAGIC.len() + 1]) << 8;
+        (version, &bytes[MAGIC.len() + 2..])
+    } else {
+        (0, bytes)
+    };
+    let map = migrate(version, payload)?;
+    map.validate()?;
+    Ok(map)
+}
+
+// чтение данных bincode с ограничением размера,
+// config() устарел в bincode 1.3, но options() нет в 1.0
+#[allow(deprecated)]
+fn deserialize<T: DeserializeOwned>(payload: &[u8]) -> Result<T, FormatError> {
+    Ok(bincode::config()
+        .limit(MAX_BINARY_SIZE)
+        .deserialize(payload)?)
+}
+
+// чтение карты из данных указанной версии с преобразованием к текущей
+fn migrate(version: u16, payload: &[u8]) -> Result<Map, FormatError> {
+    match version {
+        // версии 0 и 1 отличаются только наличием заголовка
+        0 | 1 => Ok(Map::from(deserialize::<MapV1>(payload)?)),
+        2 => Ok(Map::from(deserialize::<MapV2>(payload)?)),
+        3 => Ok(Map::from(deserialize::<MapV3>(payload)?)),
+        4 => Ok(Map::from(deserialize::<MapV4>(payload)?)),
+        5 => Ok(Map::from(deserialize::<MapV5>(payload)?)),
+        6 => Ok(Map::from(deserialize::<MapV6>(payload)?)),
+        7 => Ok(Map::from(deserialize::<MapV7>(payload)?)),
+        8 => deserialize(payload),
+        version => Err(FormatError::Version(version)),
+    }
+}
+
+// запись карты в файл, формат выбирается по расширению
+pub fn save(path: &Path, map: &Map) -> Result<(), FormatError> {
+    match MapFormat::from_path(path) {
+        MapFormat::Text => fs::write(path, to_text(map))?,
+        MapFormat::Json => fs::write(path, to_json(map)?)?,
+        MapFormat::Bincode => fs::write(path, to_binary(map)?)?,
+    }
+    Ok(())
+}
+
+// чтение карты из файла, формат выбирается по расширению,
+// карта проверяется перед тем как быть возвращённой
+pub fn load(path: &Path) -> Result<Map, FormatError> {
+    match MapFormat::from_path(path) {
+        MapFormat::Text => from_text(&fs::read_to_string(path)?),
+        MapFormat::Json => from_json(&fs::read_to_string(path)?),
+        MapFormat::Bincode => from_binary(&fs::read(path)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    fn header(version: u16) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version as u8);
+        bytes.push((version >> 8) as u8);
+        bytes
+    }
+
+    fn with_header<T: Serialize>(version: u16, payload: &T) -> Vec<u8> {
+        let mut bytes = header(version);
+        bytes.extend(bincode::serialize(payload).unwrap());
+        bytes
+    }
+
+    // клетки и веса карты вместе со стеной по периметру, как они хранятся в файле
+    fn padded(map: &Map) -> (usize, Vec<Cell>, Vec<u8>) {
+        let cols = map.cols() + 2;
+        let mut cells = vec![Cell::Impassable; (map.rows() + 2) * cols];
+        let mut weights = vec![1; cells.len()];
+        for i in 0..map.rows() {
+            for j in 0..map.cols() {
+                cells[(i + 1) * cols + j + 1] = map[i][j];
+                weights[(i + 1) * cols + j + 1] = map.weight(MapPos::new(i, j));
+            }
+        }
+        (cols, cells, weights)
+    }
+
+    #[test]
+    fn old_versions_are_migrated() {
+        let plain = from_text("S.3\n..F\n").unwrap();
+        let (cols, cells, weights) = padded(&plain);
+        let (start, finish) = (plain.start(), plain.finishes()[0]);
+        // в версии 1 весов нет, все клетки получают вес 1
+        let v1 = (cols, cells.clone(), start, finish);
+        let unweighted = from_text("S..\n..F\n").unwrap();
+        assert_eq!(
+            to_text(&from_binary(&bincode::serialize(&v1).unwrap()).unwrap()),
+            to_text(&unweighted)
+        );
+        assert_eq!(
+            to_text(&from_binary(&with_header(1, &v1)).unwrap()),
+            to_text(&unweighted)
+        );
+        let v2 = (cols, cells.clone(), weights.clone(), start, finish);
+        assert_eq!(
+            to_text(&from_binary(&with_header(2, &v2)).unwrap()),
+            to_text(&plain)
+        );
+        let v3 = (cols, cells, weights, start, vec![finish]);
+        assert_eq!(
+            to_text(&from_binary(&with_header(3, &v3)).unwrap()),
+            to_text(&plain)
+        );
+
+        let mut full = from_text("S.W\nW.F\n").unwrap();
+        let waypoints = vec![MapPos::new(1, 0), MapPos::new(0, 2)];
+        assert!(full.set_waypoints(waypoints.clone()));
+        let (cols, cells, weights) = padded(&full);
+        let (start, finishes) = (full.start(), full.finishes().to_vec());
+        let v4 = (cols, &cells, &weights, start, &finishes, &waypoints);
+        assert_eq!(
+            to_text(&from_binary(&with_header(4, &v4)).unwrap()),
+            to_text(&full)
+        );
+        let agents = vec![Agent::new(MapPos::new(0, 1), MapPos::new(1, 1))];
+        assert!(full.add_agent(agents[0]));
+        let v5 = (
+            cols, &cells, &weights, start, &finishes, &waypoints, &agents,
+        );
+        assert_eq!(
+            to_text(&from_binary(&with_header(5, &v5)).unwrap()),
+            to_text(&full)
+        );
+        full.set_topology(Topology::Hex);
+        let v6 = (
+            cols,
+            &cells,
+            &weights,
+            start,
+            &finishes,
+            &waypoints,
+            &agents,
+            Topology::Hex,
+        );
+        assert_eq!(
+            to_text(&from_binary(&with_header(6, &v6)).unwrap()),
+            to_text(&full)
+        );
+        full.set_wrap(true);
+        let v7 = (
+            cols,
+            &cells,
+            &weights,
+            start,
+            &finishes,
+            &waypoints,
+            &agents,
+            Topology::Hex,
+            true,
+        );
+        assert_eq!(
+            to_text(&from_binary(&with_header(7, &v7)).unwrap()),
+            to_text(&full)
+        );
+        assert_eq!(
+            to_text(&from_binary(&to_binary(&full).unwrap()).unwrap()),
+            to_text(&full)
+        );
+    }
+
+    #[test]
+    fn corrupted_binary_is_rejected() {
+        let map = from_text("S.W\n..F\n").unwrap();
+        let bytes = to_binary(&map).unwrap();
+        // без сигнатуры файл читается как карта версии 0 и не проходит проверку
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(from_binary(&magic).is_err());
+        let mut future = bytes.clone();
+        future[MAGIC.len()] = (VERSION + 1) as u8;
+        match from_binary(&future) {
+            Err(FormatError::Version(version)) => assert_eq!(version, VERSION + 1),
+            _ => panic!("ожидалась ошибка версии"),
+        }
+        for len in &[0, MAGIC.len() + 2, bytes.len() / 2, bytes.len() - 1] {
+            assert!(from_binary(&bytes[..*len]).is_err());
+        }
+        // длина вектора клеток больше ограничения размера и данных в файле
+        let mut huge = header(7);
+        huge.extend(bincode::serialize(&5usize).unwrap());
+        huge.extend(bincode::serialize(&(MAX_BINARY_SIZE + 1)).unwrap());
+        assert!(matches!(from_binary(&huge), Err(FormatError::Bincode(_))));
+    }
+
+    #[test]
+    fn invalid_binary_maps_are_rejected() {
+        let map = from_text("S..\n..F\n").unwrap();
+        let (cols, cells, weights) = padded(&map);
+        let (start, finish) = (map.start(), map.finishes()[0]);
+        let load = |cols: usize, cells: &[Cell], weights: &[u8]| {
+            let v3 = (cols, cells, weights, start, vec![finish]);
+            match from_binary(&with_header(3, &v3)) {
+                Err(FormatError::Invalid(err)) => err,
+                _ => panic!("ожидалась ошибка проверки карты"),
+            }
+        };
+        assert!(matches!(
+            load(0, &cells, &weights),
+            MapError::InvalidSize { .. }
+        ));
+        assert!(matches!(
+            load(cols, &cells[1..], &weights[1..]),
+            MapError::DataLength { .. }
+        ));
+        assert!(matches!(
+            load(cols, &cells, &weights[1..]),
+            MapError::DataLength { .. }
+        ));
+        let mut open = cells.clone();
+        open[1] = Cell::Passable;
+        assert_eq!(
+            load(cols, &open, &weights),
+            MapError::Border(MapPos::new(0, 1))
+        );
+    }
+
+    // карта со всеми сохраняемыми свойствами
+    fn full_map(topology: Topology) -> Map {
+        let mut map = from_text("S.W#5\n.9.#.\nW..2F\n..F..\n").unwrap();
+        assert!(map.set_waypoints(vec![MapPos::new(2, 0), MapPos::new(0, 2)]));
+        assert!(map.add_agent(Agent::new(MapPos::new(3, 0), MapPos::new(1, 4))));
+        map.set_topology(topology);
+        map.set_wrap(true);
+        let passage = Passage::new(PassageKind::Conveyor, Direction::SE);
+        map.set_passage(MapPos::new(1, 2), Some(passage)).unwrap();
+        let passage = Passage::new(PassageKind::Slope, Direction::W);
+        map.set_passage(MapPos::new(3, 3), Some(passage)).unwrap();
+        map
+    }
+
+    #[test]
+    fn text_and_json_round_trip() {
+        for &topology in &[Topology::Square, Topology::Hex] {
+            let map = full_map(topology);
+            assert_eq!(from_text(&to_text(&map)).unwrap(), map);
+            assert_eq!(from_json(&to_json(&map).unwrap()).unwrap(), map);
+            assert_eq!(from_json_value(to_json_value(&map).unwrap()).unwrap(), map);
+            assert_eq!(from_binary(&to_binary(&map).unwrap()).unwrap(), map);
+        }
+        let text = "S.W#5\n.9.#.\nW..2F\n";
+        assert_eq!(
+            to_text(&from_text(text).unwrap()),
+            format!("{}* 0 2 2 0\n", text)
+        );
+        // пустые строки и пробелы в конце строк пропускаются
+        let map = from_text("\nS.#  \n\n..F\n\n").unwrap();
+        assert_eq!(to_text(&map), "S.#\n..F\n");
+    }
+
+    #[test]
+    fn malformed_text_is_rejected() {
+        let error = |text: &str| match from_text(text) {
+            Err(FormatError::Text(message)) => message,
+            _ => panic!("ожидалась ошибка текстового формата"),
+        };
+        assert_eq!(
+            error("S..\n.x.\n..F\n"),
+            "Неизвестный символ 'x' в строке 2, столбце 2"
+        );
+        assert_eq!(
+            error("S..\n..\n..F\n"),
+            "Строка 2 имеет длину 2, ожидалось 3"
+        );
+        assert_eq!(
+            error("S..\n...\n..F.\n"),
+            "Строка 3 имеет длину 4, ожидалось 3"
+        );
+        assert!(error("S.F\n@ 0 1\n").contains("агента"));
+        assert!(error("S.F\n> 0 1 conveyor up\n").contains("направленной клетки"));
+        assert!(error("~ round\nS.F\n").contains("форма"));
+        // карта без начальной клетки или пустая
+        assert!(matches!(from_text("..F\n"), Err(FormatError::Invalid(_))));
+        assert!(from_text("").is_err());
+    }
+
+    #[test]
+    fn malformed_json_is_rejected() {
+        let json = |rows: usize, cols: usize, cells: &str| {
+            format!(
+                "{{\"rows\": {}, \"cols\": {}, \"cells\": [{}]}}",
+                rows, cols, cells
+            )
+        };
+        assert!(from_json(&json(2, 3, "\"S..\", \"..F\"")).is_ok());
+        for text in &[
+            json(3, 3, "\"S..\", \"..F\""),
+            json(2, 4, "\"S..\", \"..F\""),
+            json(2, 3, "\"S..\", \"..F.\""),
+            json(2, 3, "\"S.?\", \"..F\""),
+            "{\"rows\": 2}".to_string(),
+            "[]".to_string(),
+        ] {
+            assert!(from_json(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn text_keeps_waypoint_order() {
+        let mut map = from_text("S.W\nW.F\n.W.\n").unwrap();
+        assert_eq!(
+            map.waypoints(),
+            &[MapPos::new(0, 2), MapPos::new(1, 0), MapPos::new(2, 1)]
+        );
+        let order = vec![MapPos::new(2, 1), MapPos::new(0, 2), MapPos::new(1, 0)];
+        assert!(map.set_waypoints(order.clone()));
+        let text = to_text(&map);
+        assert_eq!(from_text(&text).unwrap().waypoints(), order.as_slice());
+        assert_eq!(
+            from_json(&to_json(&map).unwrap()).unwrap().waypoints(),
+            order.as_slice()
+        );
+        // порядок должен перечислять все промежуточные клетки и только их
+        assert!(from_text("S.W\nW.F\n.W.\n* 2 1 0 2 1 1\n").is_err());
+        assert!(from_text("S.W\nW.F\n.W.\n* 2 1 0 2\n").is_err());
+        assert!(from_text("S.W\nW.F\n.W.\n* 2 1 0 2 1\n").is_err());
+    }
+}
@@ -1,13 +1,31 @@
-use bincode::{deserialize, serialize};
+use alternatives::Alternative;
+use flow::FlowField;
+use format::{self, MapFormat};
 use gdk::EventMask;
 use gtk::ContainerExt;
 use gtk::{ButtonExt, ComboBoxExt, ComboBoxTextExt, DialogExt};
-use gtk::{DrawingArea, FileChooserExt, GridExt, GtkWindowExt, Inhibit};
+use gtk::{DrawingArea, FileChooserExt, FileFilterExt, GridExt, GtkWindowExt, Inhibit};
 use gtk::{LabelExt, NativeDialogExt, NotebookExtManual, TextBufferExt, WidgetExt};
-use map::{Algorithm, Cell, Map, MapPos, SearchStatus, ShortestPath};
+use gtk::{SpinButtonExt, ToggleButtonExt};
+use hex;
+use import::{self, ImportOptions, PixelMapping};
+use live::LivePlanner;
+use map::{
+    Agent, Cell, Direction, GoalMode, Map, MapPos, Passage, PassageKind, SearchStatus,
+    ShortestPath, Topology, MAX_WEIGHT,
+};
+use mapf::{self, Method, Solution};
 use relm::{interval, DrawHandler, Relm, Widget};
 use relm_attributes::widget;
-use std::fs;
+use render::{Renderer, EXPORT_CELL_SIZE};
+use route::{self, Route};
+use search::{Registry, SearchEvent, DEFAULT_ALGORITHM};
+use smooth::{self, Point, Smoothing};
+use std::collections::VecDeque;
+use std::mem;
+use trace::{self, Replay, Trace};
+use worker::{AlternativesWorker, SearchWorker, WorkerMessage};
+use world::{LinkKind, World, WorldPath, WorldPos};
 
 // макрос для распаковки Result или вывода окна с ошибкой
 macro_rules! try_message {
@@ -22,84 +40,80 @@ macro_rules! try_message {
     };
 }
 
-#[derive(Debug, Clone)]
-struct Color {
-    red: f64,
-    green: f64,
-    blue: f64,
-}
-
-impl Color {
-    fn white() -> Self {
-        Self {
-            red: 1f64,
-            green: 1f64,
-            blue: 1f64,
-        }
-    }
-
-    fn red() -> Self {
-        Self {
-            red: 1f64,
-            green: 0f64,
-            blue: 0f64,
-        }
-    }
-
-    fn green() -> Self {
-        Self {
-            red: 0f64,
-            green: 1f64,
-            blue: 0f64,
-        }
-    }
-
-    fn grey() -> Self {
-        Self {
-            red: 0.5f64,
-            green: 0.5f64,
-            blue: 0.5f64,
-        }
-    }
-
-    fn yellow() -> Self {
-        Self {
-            red: 1f64,
-            green: 1f64,
-            blue: 0f64,
-        }
-    }
-
-    fn pale_blue() -> Self {
-        Self {
-            red: 0.68359375f64,
-            green: 0.9296875f64,
-            blue: 0.9296875f64,
-        }
-    }
-
-    fn pale_green() -> Self {
-        Self {
-            red: 0.59375f64,
-            green: 0.98046875f64,
-            blue: 0.59375f64,
-        }
-    }
-}
-
 // состояние указателя мыши
 struct Cursor {
     position: (f64, f64),
     button_pressed: bool,
     cell: Cell,
+    // текущее положение перетаскиваемой клетки
+    drag: Option<MapPos>,
 }
 
+// инструменты редактирования карты
+const TOOL_WALLS: i32 = 0;
+const TOOL_GOALS: i32 = 1;
+const TOOL_WAYPOINTS: i32 = 2;
+const TOOL_AGENTS: i32 = 3;
+const TOOL_STAIRS: i32 = 4;
+const TOOL_ELEVATORS: i32 = 5;
+const TOOL_PORTALS: i32 = 6;
+const TOOL_CONVEYORS: i32 = 7;
+const TOOL_RAMPS: i32 = 8;
+const TOOL_SLOPES: i32 = 9;
+
+// число альтернативных путей, предлагаемых после поиска
+const ALTERNATIVES: usize = 5;
+
+// шагов агентов за одно сообщение Msg::Next
+const AGENT_SPEED: f64 = 0.1;
+
+// событий фонового поиска, показываемых за одно сообщение Msg::Next: не меньше
+// WORKER_EVENTS и не меньше доли 1 / WORKER_CATCH_UP ещё не показанных, поэтому
+// отставание анимации от быстрого поиска сокращается за несколько десятков сообщений
+const WORKER_EVENTS: usize = 64;
+const WORKER_CATCH_UP: usize = 30;
+// при большем числе непоказанных событий анимация пропускается
+// и сразу показывается результат поиска
+const MAX_PENDING_EVENTS: usize = 1_000_000;
+
 // модель виджета
 pub struct Model {
     draw_handler: DrawHandler<DrawingArea>,
+    // алгоритмы поиска, доступные в интерфейсе
+    registry: Registry,
     search: ShortestPath,
     status: SearchStatus,
-    path: Option<Vec<MapPos>>,
+    paths: Vec<Vec<MapPos>>,
+    // маршрут через промежуточные клетки
+    route: Option<Route>,
+    // альтернативные пути в порядке возрастания стоимости
+    // и их поиск в отдельном потоке
+    alternatives: Vec<Alternative>,
+    alternatives_worker: Option<AlternativesWorker>,
+    // пути агентов и текущий момент их движения
+    solution: Option<Solution>,
+    time: f64,
+    // начальная клетка добавляемого агента, ожидающего выбора цели
+    agent_start: Option<MapPos>,
+    // воспроизводимая трасса поиска
+    replay: Option<Replay>,
+    // поиск в отдельном потоке, ещё не показанные события
+    // и результат, ожидающий окончания анимации
+    worker: Option<SearchWorker>,
+    pending: VecDeque<SearchEvent>,
+    finished: Option<(ShortestPath, SearchStatus)>,
+    skip_animation: bool,
+    // повторный поиск после каждого изменения карты
+    live: Option<LivePlanner>,
+    // поле направлений, построенное при отрисовке, сбрасывается при изменении карты
+    flow: Option<FlowField>,
+    // слои многоуровневой карты, отображаемый слой редактируется в search.map
+    // и копируется в world перед поиском и переключением слоя
+    world: World,
+    layer: usize,
+    world_path: Option<WorldPath>,
+    // первый конец добавляемого перехода между клетками
+    link_start: Option<WorldPos>,
     cursor: Cursor,
 }
 
@@ -108,17 +122,31 @@ pub struct Model {
 pub enum Msg {
     About,
     AlgorithmChange,
+    AlternativeChange,
     ButtonPress,
     ButtonRelease,
     Clear,
     ClearPath,
+    Export,
     FindPath,
+    GoalModeChange,
+    Import,
+    LayerAdd,
+    LayerChange,
+    LayerRemove,
+    LiveChange,
     MoveCursor((f64, f64)),
     Next,
     Open,
     Quit,
+    RecordTrace,
+    ReplayTrace,
     Save,
+    SkipAnimation,
+    SmoothingChange,
+    TopologyChange,
     UpdateDrawBuffer,
+    WrapChange,
 }
 
 impl Win {
@@ -126,6 +154,19 @@ impl Win {
     fn get_cursor_pos(&self) -> MapPos {
         // размер отображаемой карты
         let allocation = self.drawing_area.get_allocation();
+        let map = &self.model.search.map;
+        if map.topology() == Topology::Hex {
+            let (cols, rows) = hex::extent(map.rows(), map.cols());
+            let (x, y) = hex::pixel_to_offset(
+                self.model.cursor.position.0,
+                self.model.cursor.position.1,
+                f64::from(allocation.width) / cols,
+                f64::from(allocation.height) / rows,
+            );
+            // указатель за пределами карты выбирает ближайшую крайнюю клетку
+            let clamp = |v: isize, len: usize| v.max(0).min(len as isize - 1) as usize;
+            return MapPos::new(clamp(x, map.rows()), clamp(y, map.cols()));
+        }
         let x = match self.model.cursor.position.1.round() {
             x if x < 0f64 => 0,
             x if x >= f64::from(allocation.height) => self.model.search.map.rows() - 1,
@@ -139,6 +180,314 @@ impl Win {
         MapPos::new(x, y)
     }
 
+    // режим достижения конечных клеток, выбранный пользователем
+    fn goal_mode(&self) -> GoalMode {
+        if self.all_goals_check.get_active() {
+            GoalMode::All
+        } else {
+            GoalMode::Nearest
+        }
+    }
+
+    // выбранный способ сглаживания пути
+    fn smoothing(&self) -> Smoothing {
+        Smoothing::ALL
+            .get(self.smooth_combo.get_active() as usize)
+            .cloned()
+            .unwrap_or(Smoothing::Raw)
+    }
+
+    // найденные пути после сглаживания
+    fn curves(&self) -> Vec<Vec<Point>> {
+        let smoothing = self.smoothing();
+        self.model
+            .paths
+            .iter()
+            .map(|p| smooth::smooth(&self.model.search.map, p, smoothing))
+            .collect()
+    }
+
+    // отображаемые пути: исходные без сглаживания или сглаженные
+    fn visible_paths(&self) -> (&[Vec<MapPos>], Vec<Vec<Point>>) {
+        if self.smoothing() == Smoothing::Raw {
+            (&self.model.paths, Vec::new())
+        } else {
+            (&[], self.curves())
+        }
+    }
+
+    // выводит длину первого пути до и после сглаживания
+    fn show_smoothed_length(&self) {
+        if let (Some(path), Some(curve)) = (self.model.paths.first(), self.curves().first()) {
+            let raw = smooth::smooth(&self.model.search.map, path, Smoothing::Raw);
+            self.label.set_text(&format!(
+                "Длина: {:.2}, после сглаживания: {:.2}",
+                smooth::length(&raw),
+                smooth::length(curve)
+            ));
+        }
+    }
+
+    // показ найденных путей, альтернатив и длины пути после завершения поиска
+    fn show_found(&mut self, len: f64) {
+        self.model.status = SearchStatus::Found(len);
+        self.model.paths = self
+            .model
+            .search
+            .paths()
+            .into_iter()
+            .map(|(_, _, path)| path)
+            .collect();
+        let expanded = self.model.search.stats().expanded;
+        let text = match self.model.paths.len() {
+            1 => format!("Длина пути: {:.2}, раскрыто клеток: {}", len, expanded),
+            n => format!(
+                "Длина пути: {:.2}, целей достигнуто: {}, раскрыто клеток: {}",
+                len, n, expanded
+            ),
+        };
+        self.label.set_text(text.as_str());
+        // альтернативы предлагаются для пути до ближайшей цели,
+        // список заполняется в Msg::Next после завершения их поиска
+        if self.goal_mode() == GoalMode::Nearest {
+            let map = self.model.search.map.clone();
+            self.model.alternatives_worker = Some(AlternativesWorker::spawn(map, ALTERNATIVES));
+        }
+        if self.smoothing() != Smoothing::Raw {
+            self.show_smoothed_length();
+        }
+    }
+
+    // вид перехода, добавляемого выбранным инструментом
+    fn link_kind(&self) -> Option<LinkKind> {
+        match self.tool_combo.get_active() {
+            TOOL_STAIRS => Some(LinkKind::Stairs),
+            TOOL_ELEVATORS => Some(LinkKind::Elevator),
+            TOOL_PORTALS => Some(LinkKind::Portal),
+            _ => None,
+        }
+    }
+
+    // вид направленной клетки, задаваемой выбранным инструментом
+    fn passage_kind(&self) -> Option<PassageKind> {
+        match self.tool_combo.get_active() {
+            TOOL_CONVEYORS => Some(PassageKind::Conveyor),
+            TOOL_RAMPS => Some(PassageKind::Ramp),
+            TOOL_SLOPES => Some(PassageKind::Slope),
+            _ => None,
+        }
+    }
+
+    // копирование отображаемого слоя в многоуровневую карту
+    fn store_layer(&mut self) {
+        if let Some(map) = self.model.world.layer_mut(self.model.layer) {
+            map.replace_from(&self.model.search.map);
+            map.clear_path();
+        }
+    }
+
+    // список слоёв с выбранным отображаемым слоем
+    fn show_layers(&self) {
+        self.layer_combo.remove_all();
+        for i in 0..self.model.world.layers().len() {
+            self.layer_combo.append_text(&format!("Слой {}", i + 1));
+        }
+        self.layer_combo.set_active(self.model.layer as i32);
+    }
+
+    // загруженная карта становится единственным слоем
+    fn reset_world(&mut self) {
+        self.model.world = World::new(self.model.search.map.clone());
+        self.model.layer = 0;
+        self.model.link_start = None;
+        self.show_layers();
+    }
+
+    // путь по всем слоям, на отображаемом слое показываются его участки
+    fn find_world_path(&mut self) {
+        self.store_layer();
+        self.model.world_path = self.model.world.shortest_path();
+        match self.model.world_path {
+            Some(ref path) => {
+                self.model.paths = path.segments(self.model.layer);
+                self.model.status = SearchStatus::Found(path.cost);
+                self.label
+                    .set_text(&format!("Длина пути по слоям: {:.2}", path.cost));
+            }
+            None => {
+                self.model.paths.clear();
+                self.model.status = SearchStatus::NotFound;
+                self.label.set_text("Путь по слоям не найден");
+            }
+        }
+    }
+
+    // повторный поиск в живом режиме, cells_changed - изменились стены
+    // или веса клеток и сохранённые деревья путей нужно построить заново;
+    // вызывается после каждого изменения карты, поэтому сбрасывает и поле направлений
+    fn replan(&mut self, cells_changed: bool) {
+        self.model.flow = None;
+        if self.model.live.is_some() && !self.model.world.is_flat() {
+            self.find_world_path();
+            return;
+        }
+        let goal_mode = self.goal_mode();
+        let live = match self.model.live {
+            Some(ref mut live) => live,
+            None => return,
+        };
+        if cells_changed {
+            live.invalidate();
+        }
+        let map = &self.model.search.map;
+        // маршрут через промежуточные клетки строится заново целиком
+        if !map.waypoints().is_empty() {
+            self.model.paths.clear();
+            self.model.route = route::plan(map);
+            match self.model.route {
+                Some(ref route) => self
+                    .label
+                    .set_text(&format!("Длина маршрута: {:.2}", route.length())),
+                None => self.label.set_text("Маршрут не найден"),
+            }
+            return;
+        }
+        self.model.route = None;
+        let paths = live.plan(map, goal_mode);
+        match paths.first() {
+            Some(&(_, len, _)) => self.label.set_text(&format!(
+                "Длина пути: {:.2}, построено деревьев: {}",
+                len,
+                live.builds()
+            )),
+            None => self.label.set_text("Путь не найден"),
+        }
+        self.model.paths = paths.into_iter().map(|(_, _, path)| path).collect();
+        if self.smoothing() != Smoothing::Raw {
+            self.show_smoothed_length();
+        }
+    }
+
+    // переключатели формы клеток и соединения краёв по загруженной карте
+    fn show_topology(&self) {
+        self.hex_check
+            .set_active(self.model.search.map.topology() == Topology::Hex);
+        self.wrap_check.set_active(self.model.search.map.wrap());
+    }
+
+    // блокирует элементы управления на время поиска пути
+    fn set_controls_sensitive(&self, sensitive: bool) {
+        self.drawing_area.set_sensitive(sensitive);
+        self.combo_box.set_sensitive(sensitive);
+        self.tool_combo.set_sensitive(sensitive);
+        self.all_goals_check.set_sensitive(sensitive);
+        self.optimize_check.set_sensitive(sensitive);
+        self.save_button.set_sensitive(sensitive);
+        self.open_button.set_sensitive(sensitive);
+        self.import_button.set_sensitive(sensitive);
+        self.clear_button.set_sensitive(sensitive);
+        self.record_trace_button.set_sensitive(sensitive);
+        self.background_check.set_sensitive(sensitive);
+        self.live_check.set_sensitive(sensitive);
+        self.hex_check.set_sensitive(sensitive);
+        self.wrap_check.set_sensitive(sensitive);
+        self.layer_combo.set_sensitive(sensitive);
+        self.add_layer_button.set_sensitive(sensitive);
+        self.remove_layer_button.set_sensitive(sensitive);
+        self.replay_trace_button.set_sensitive(sensitive);
+    }
+
+    // добавляет фильтр файлов трассы в диалог выбора файла
+    fn add_trace_filter(file_chooser: &gtk::FileChooserNative) {
+        let filter = gtk::FileFilter::new();
+        filter.set_name("Трасса поиска (JSON Lines)");
+        filter.add_pattern("*.jsonl");
+        file_chooser.add_filter(&filter);
+    }
+
+    // добавляет фильтры форматов карты в диалог выбора файла
+    fn add_map_filters(file_chooser: &gtk::FileChooserNative) {
+        for format in MapFormat::ALL.iter() {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(format.name());
+            filter.add_pattern(&format!("*.{}", format.extension()));
+            file_chooser.add_filter(&filter);
+        }
+        let filter = gtk::FileFilter::new();
+        filter.set_name("Все файлы");
+        filter.add_pattern("*");
+        file_chooser.add_filter(&filter);
+    }
+
+    // запрашивает параметры импорта изображения,
+    // возвращает None если пользователь отменил импорт
+    fn import_options(&self) -> Option<ImportOptions> {
+        let dialog = gtk::Dialog::new_with_buttons(
+            Some("Параметры импорта"),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL,
+            &[
+                ("Импорт", gtk::ResponseType::Accept.into()),
+                ("Отменить", gtk::ResponseType::Cancel.into()),
+            ],
+        );
+        let mapping = gtk::ComboBoxText::new();
+        mapping.append_text("Порог яркости");
+        mapping.append_text("Палитра программы");
+        mapping.set_active(0);
+        let threshold = gtk::SpinButton::new_with_range(0f64, 100f64, 1f64);
+        threshold.set_value(50f64);
+        let max_weight = gtk::SpinButton::new_with_range(1f64, f64::from(MAX_WEIGHT), 1f64);
+        max_weight.set_value(f64::from(MAX_WEIGHT));
+        let cell_size = gtk::SpinButton::new_with_range(1f64, 64f64, 1f64);
+        cell_size.set_value(1f64);
+        let max_size = gtk::SpinButton::new_with_range(2f64, 1000f64, 1f64);
+        max_size.set_value(200f64);
+
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(4);
+        grid.set_row_spacing(2);
+        let labels = [
+            "Преобразование цветов",
+            "Порог стены, %",
+            "Максимальный вес клетки",
+            "Размер клетки, пикселей",
+            "Максимальный размер карты",
+        ];
+        for (i, text) in labels.iter().enumerate() {
+            let label = gtk::Label::new(Some(*text));
+            label.set_xalign(0f32);
+            grid.attach(&label, 0, i as i32, 1, 1);
+        }
+        grid.attach(&mapping, 1, 0, 1, 1);
+        grid.attach(&threshold, 1, 1, 1, 1);
+        grid.attach(&max_weight, 1, 2, 1, 1);
+        grid.attach(&cell_size, 1, 3, 1, 1);
+        grid.attach(&max_size, 1, 4, 1, 1);
+        dialog.get_content_area().add(&grid);
+        dialog.show_all();
+
+        let accepted = dialog.run() == gtk::ResponseType::Accept.into();
+        let max_size = max_size.get_value_as_int() as usize;
+        let options = ImportOptions {
+            mapping: match mapping.get_active() {
+                0 => PixelMapping::Threshold {
+                    wall: threshold.get_value() / 100f64,
+                    max_weight: max_weight.get_value_as_int() as u8,
+                },
+                _ => PixelMapping::default_palette(),
+            },
+            cell_size: cell_size.get_value_as_int() as usize,
+            max_size: Some((max_size, max_size)),
+        };
+        dialog.destroy();
+        if accepted {
+            Some(options)
+        } else {
+            None
+        }
+    }
+
     // выводит сообщение об успехе
     fn success_message(&self, message: &str) {
         let dialog = gtk::MessageDialog::new(
@@ -170,11 +519,35 @@ impl Win {
 impl Widget for Win {
     // инициализация элементов виджета
     fn init_view(&mut self) {
-        self.combo_box.append_text("Поиск в ширину");
-        self.combo_box
-            .append_text("Алгоритм Дейкстры");
-        self.combo_box.append_text("А*");
-        self.combo_box.set_active(2);
+        for entry in self.model.registry.entries() {
+            self.combo_box.append_text(entry.title);
+        }
+        let default = self
+            .model
+            .registry
+            .entries()
+            .iter()
+            .position(|e| e.name == DEFAULT_ALGORITHM)
+            .unwrap_or(0);
+        self.combo_box.set_active(default as i32);
+
+        self.tool_combo.append_text("Стены");
+        self.tool_combo.append_text("Цели");
+        self.tool_combo.append_text("Точки");
+        self.tool_combo.append_text("Агенты");
+        self.tool_combo.append_text("Лестницы");
+        self.tool_combo.append_text("Лифты");
+        self.tool_combo.append_text("Порталы");
+        self.tool_combo.append_text("Конвейеры");
+        self.tool_combo.append_text("Пандусы");
+        self.tool_combo.append_text("Склоны");
+        self.tool_combo.set_active(TOOL_WALLS);
+        self.show_layers();
+
+        for smoothing in Smoothing::ALL.iter() {
+            self.smooth_combo.append_text(smoothing.name());
+        }
+        self.smooth_combo.set_active(0);
 
         self.model.draw_handler.init(&self.drawing_area);
         self.drawing_area.add_events(
@@ -186,16 +559,42 @@ impl Widget for Win {
     }
 
     // инициализация модели виджета
-    fn model(size: (usize, usize)) -> Model {
+    // параметры: размер карты и реестр алгоритмов поиска
+    fn model(param: (usize, usize, Registry)) -> Model {
+        let (rows, cols, registry) = param;
+        let algorithm = registry
+            .create(DEFAULT_ALGORITHM)
+            .or_else(|| registry.entries().first().map(|e| e.create()))
+            .expect("empty algorithm registry");
+        let map = Map::new(rows, cols).expect("map size");
         Model {
             draw_handler: DrawHandler::new().expect("draw handler"),
-            search: ShortestPath::new(Map::new(size.0, size.1), Algorithm::AStar),
+            world: World::new(map.clone()),
+            layer: 0,
+            world_path: None,
+            link_start: None,
+            search: ShortestPath::new(map, algorithm),
+            registry,
             status: SearchStatus::NotFound,
-            path: None,
+            paths: Vec::new(),
+            route: None,
+            alternatives: Vec::new(),
+            alternatives_worker: None,
+            solution: None,
+            time: 0f64,
+            agent_start: None,
+            replay: None,
+            worker: None,
+            pending: VecDeque::new(),
+            finished: None,
+            skip_animation: false,
+            live: None,
+            flow: None,
             cursor: Cursor {
                 position: (0f64, 0f64),
                 button_pressed: false,
                 cell: Cell::Passable,
+                drag: None,
             },
         }
     }
@@ -216,7 +615,45 @@ impl Widget for Win {
                      Для удаление препятствий нажите на серую клетку и\n\
                      не отпуская двигайте мышкой по препятствиям.\n\n\
                      Перетащите зеленую клетку, чтобы установить начальную позицию.\n\n\
-                     Перетащите красную клетку, чтобы установить конечную позицию."
+                     Перетащите красную клетку, чтобы установить конечную позицию.\n\n\
+                     Инструментом «Цели» добавьте или удалите конечные клетки,\n\
+                     путь строится до ближайшей из них или до всех сразу.\n\n\
+                     Инструментом «Точки» добавьте или удалите промежуточные клетки,\n\
+                     путь пройдёт через них в порядке добавления или в порядке\n\
+                     наименьшей длины маршрута, если он оптимизируется.\n\n\
+                     Инструментом «Агенты» выберите начальную, а затем целевую\n\
+                     клетку агента, нажатие на клетку агента удаляет его.\n\
+                     Пути агентов строятся без столкновений.\n\n\
+                     «Поле направлений» показывает расстояние от каждой клетки\n\
+                     до ближайшей цели и направление следующего шага к ней.\n\n\
+                     После поиска в списке путей можно выбрать один из\n\
+                     альтернативных путей, упорядоченных по стоимости.\n\n\
+                     Найденный путь можно сгладить: сократить точки на прямых\n\
+                     участках, натянуть нить между видимыми клетками или\n\
+                     скруглить углы кривой Чайкина.\n\n\
+                     «Записать трассу» сохраняет события поиска выбранным\n\
+                     алгоритмом в файл, «Воспроизвести трассу» загружает карту\n\
+                     и события из файла и показывает их без повторного поиска.\n\n\
+                     «Фоновый поиск» выполняет поиск в отдельном потоке, окно\n\
+                     не блокируется, «Пропустить анимацию» сразу показывает\n\
+                     результат, «Очистить путь» отменяет поиск.\n\n\
+                     В режиме «Живой поиск» путь строится заново при каждом\n\
+                     перемещении начальной или конечной клетки и изменении стен.\n\n\
+                     «Шестиугольники» заменяет квадратные клетки шестиугольными\n\
+                     с шестью соседями, нечётные строки сдвинуты вправо.\n\
+                     «Замкнуть края» соединяет края карты с противоположными,\n\
+                     путь может уйти за край и продолжиться с другой стороны.\n\n\
+                     Карта может состоять из нескольких слоёв: путь ведёт от\n\
+                     начальной клетки первого слоя к конечной клетке последнего.\n\
+                     Инструменты «Лестницы», «Лифты» и «Порталы» соединяют две\n\
+                     клетки любых слоёв, выбранные по очереди, повторное нажатие\n\
+                     на конец перехода удаляет его.\n\n\
+                     Инструменты «Конвейеры», «Пандусы» и «Склоны» задают\n\
+                     направление клетки, каждое нажатие поворачивает стрелку\n\
+                     по часовой стрелке, после последнего направления клетка\n\
+                     становится обычной. С конвейера можно сойти только по\n\
+                     стрелке, на пандус - только войти по стрелке, склон\n\
+                     спускается по стрелке, и подъём по нему вдвое дороже."
                 );
                 let view_instruction = gtk::TextView::new_with_buffer(&text_instruction);
 
@@ -272,70 +709,490 @@ impl Widget for Win {
                 dialog.show_all();
             }
             Msg::AlgorithmChange => {
-                let map = self.model.search.map.clone();
-                match self.combo_box.get_active() {
-                    0 => self.model.search = ShortestPath::new(map, Algorithm::BreadthFirstSearch),
-                    1 => self.model.search = ShortestPath::new(map, Algorithm::Dijkstra),
-                    _ => self.model.search = ShortestPath::new(map, Algorithm::AStar),
+                let index = self.combo_box.get_active();
+                if let Some(entry) = self.model.registry.entries().get(index as usize) {
+                    self.model.search.set_algorithm(entry.create());
+                }
+                let goal_mode = self.goal_mode();
+                self.model.search.set_goal_mode(goal_mode);
+            }
+            // выделение выбранного альтернативного пути
+            Msg::AlternativeChange => {
+                let index = self.alternatives_combo.get_active();
+                if let Some(alternative) = self.model.alternatives.get(index as usize) {
+                    self.model.paths = vec![alternative.path.clone()];
+                    self.label
+                        .set_text(&format!("Длина пути: {:.2}", alternative.cost));
+                    if self.smoothing() != Smoothing::Raw {
+                        self.show_smoothed_length();
+                    }
                 }
             }
             Msg::ButtonPress => {
-                self.model.cursor.button_pressed = true;
                 let pos = self.get_cursor_pos();
-                self.model.cursor.cell = match self.model.search.map[pos.x][pos.y] {
+                let cell = self.model.search.map[pos.x][pos.y];
+                // добавление перехода в два нажатия, второй конец может
+                // быть выбран на другом слое, и удаление переходов
+                if let Some(kind) = self.link_kind() {
+                    let here = WorldPos::new(self.model.layer, pos);
+                    if let Some(start) = self.model.link_start.take() {
+                        self.label.set_text("Длина пути:");
+                        try_message!(self.model.world.add_link(kind, start, here));
+                    } else if self.model.world.remove_links(here) {
+                        self.label.set_text("Длина пути:");
+                    } else if cell != Cell::Impassable {
+                        self.model.link_start = Some(here);
+                        self.label.set_text("Выберите второй конец перехода");
+                        return;
+                    }
+                    self.replan(false);
+                    return;
+                }
+                // поворот направления клетки по часовой стрелке,
+                // после последнего направления клетка становится обычной
+                if let Some(kind) = self.passage_kind() {
+                    if cell != Cell::Impassable {
+                        let map = &mut self.model.search.map;
+                        let topology = map.topology();
+                        let mut directions =
+                            Direction::ALL.iter().cloned().filter(|d| d.fits(topology));
+                        let direction = match try_message!(map.passage(pos)) {
+                            Some(p) if p.kind == kind => directions
+                                .find(|d| *d == p.direction)
+                                .and_then(|_| directions.next()),
+                            _ => directions.next(),
+                        };
+                        let passage = direction.map(|d| Passage::new(kind, d));
+                        try_message!(map.set_passage(pos, passage));
+                        self.replan(true);
+                    }
+                    return;
+                }
+                // добавление и удаление конечных клеток
+                if self.tool_combo.get_active() == TOOL_GOALS && cell != Cell::Start {
+                    if cell == Cell::Finish {
+                        self.model.search.map.remove_finish(pos);
+                    } else {
+                        self.model.search.map.set_cell(Cell::Finish, pos);
+                    }
+                    self.replan(cell != Cell::Passable);
+                    return;
+                }
+                // добавление и удаление промежуточных клеток
+                if self.tool_combo.get_active() == TOOL_WAYPOINTS {
+                    if cell == Cell::Waypoint {
+                        self.model.search.map.remove_waypoint(pos);
+                    } else {
+                        self.model.search.map.set_cell(Cell::Waypoint, pos);
+                    }
+                    self.replan(cell != Cell::Passable);
+                    return;
+                }
+                // добавление агента в два нажатия и удаление агентов
+                if self.tool_combo.get_active() == TOOL_AGENTS {
+                    let map = &mut self.model.search.map;
+                    if map.is_agent_cell(pos) {
+                        map.remove_agent(pos);
+                        self.model.agent_start = None;
+                    } else if let Some(start) = self.model.agent_start.take() {
+                        map.add_agent(Agent::new(start, pos));
+                        self.label.set_text("Длина пути:");
+                    } else if cell != Cell::Impassable {
+                        self.model.agent_start = Some(pos);
+                        self.label.set_text("Выберите цель агента");
+                    }
+                    return;
+                }
+                self.model.cursor.button_pressed = true;
+                self.model.cursor.drag = Some(pos);
+                self.model.cursor.cell = match cell {
                     Cell::Passable => Cell::Impassable,
                     Cell::Impassable => Cell::Passable,
                     c => c,
                 };
-                self.model.search.map[pos.x][pos.y] = self.model.cursor.cell;
+                self.model.search.map.set_cell(self.model.cursor.cell, pos);
+                let cell = self.model.cursor.cell;
+                self.replan(cell == Cell::Passable || cell == Cell::Impassable);
             }
             Msg::ButtonRelease => self.model.cursor.button_pressed = false,
             Msg::Clear => {
                 self.model.search.map.clear();
+                self.replan(true);
             }
             Msg::ClearPath => {
                 self.search_path_button.show();
                 self.clear_path_button.hide();
-                self.drawing_area.set_sensitive(true);
-                self.combo_box.set_sensitive(true);
-                self.save_button.set_sensitive(true);
-                self.open_button.set_sensitive(true);
-                self.clear_button.set_sensitive(true);
-                self.model.path = None;
+                self.set_controls_sensitive(true);
+                self.model.paths.clear();
+                self.model.route = None;
+                self.model.alternatives.clear();
+                self.model.alternatives_worker = None;
+                self.alternatives_combo.remove_all();
+                self.alternatives_combo.set_sensitive(false);
+                self.model.solution = None;
+                self.model.time = 0f64;
+                self.model.replay = None;
+                // удаление потока поиска отменяет незавершённый поиск
+                self.model.worker = None;
+                self.model.pending.clear();
+                self.model.finished = None;
+                self.skip_button.set_sensitive(false);
                 self.model.search.map.clear_path();
+                self.model.world_path = None;
                 // сообщения Msg::Next не будут обрабатываться
                 self.model.status = SearchStatus::NotFound;
                 self.label.set_text("Длина пути:");
             }
+            Msg::Export => {
+                let file_chooser = gtk::FileChooserNative::new(
+                    Some("Экспорт изображения"),
+                    Some(&self.window),
+                    gtk::FileChooserAction::Save,
+                    Some("Сохранить"),
+                    Some("Отменить"),
+                );
+                for (name, extension) in [("PNG", "png"), ("SVG", "svg")].iter() {
+                    let filter = gtk::FileFilter::new();
+                    filter.set_name(name);
+                    filter.add_pattern(&format!("*.{}", extension));
+                    file_chooser.add_filter(&filter);
+                }
+                if file_chooser.run() == gtk::ResponseType::Accept.into() {
+                    let mut path = file_chooser.get_filename().unwrap();
+                    if path.extension().is_none() {
+                        let extension = file_chooser
+                            .get_filter()
+                            .and_then(|f| f.get_name())
+                            .map(|n| n.to_lowercase())
+                            .unwrap_or_else(|| "png".to_string());
+                        path.set_extension(extension);
+                    }
+                    let (paths, curves) = self.visible_paths();
+                    try_message!(
+                        Renderer::new(&self.model.search.map)
+                            .with_links(self.model.world.links(), self.model.layer)
+                            .with_paths(paths)
+                            .with_curves(&curves)
+                            .with_route(self.model.route.as_ref())
+                            .with_agents(self.model.solution.as_ref(), self.model.time)
+                            .export(&path, EXPORT_CELL_SIZE)
+                    );
+                    self.success_message("Изображение сохранено");
+                }
+            }
             Msg::FindPath => {
-                // инициализация поиска
-                self.model.search.init();
                 self.search_path_button.hide();
                 self.clear_path_button.show();
-                self.drawing_area.set_sensitive(false);
-                self.combo_box.set_sensitive(false);
-                self.save_button.set_sensitive(false);
-                self.open_button.set_sensitive(false);
-                self.clear_button.set_sensitive(false);
+                self.set_controls_sensitive(false);
+                // путь по слоям многоуровневой карты строится сразу
+                if !self.model.world.is_flat() {
+                    self.find_world_path();
+                    return;
+                }
+                // пути агентов строятся сразу, а их движение показывается
+                // в сообщениях Msg::Next
+                if !self.model.search.map.agents().is_empty() {
+                    self.model.agent_start = None;
+                    self.model.time = 0f64;
+                    self.model.solution = mapf::solve(&self.model.search.map);
+                    match self.model.solution {
+                        Some(ref solution) => {
+                            let method = match solution.method {
+                                Method::ConflictBased => "CBS",
+                                Method::Prioritized => "приоритеты",
+                            };
+                            self.model.status = SearchStatus::Searching;
+                            self.label.set_text(&format!(
+                                "Шагов: {}, время: {} ({})",
+                                solution.cost(),
+                                solution.makespan(),
+                                method
+                            ));
+                        }
+                        None => {
+                            self.model.status = SearchStatus::NotFound;
+                            self.label.set_text("Пути агентов не найдены");
+                        }
+                    }
+                    return;
+                }
+                // маршрут через промежуточные клетки строится сразу
+                if !self.model.search.map.waypoints().is_empty() {
+                    let map = &mut self.model.search.map;
+                    self.model.route = if self.optimize_check.get_active() {
+                        route::optimize(map)
+                    } else {
+                        route::plan(map)
+                    };
+                    match self.model.route {
+                        Some(ref route) => {
+                            map.set_waypoints(route.order.clone());
+                            self.model.status = SearchStatus::Found(route.length());
+                            self.label
+                                .set_text(&format!("Длина маршрута: {:.2}", route.length()));
+                        }
+                        None => {
+                            self.model.status = SearchStatus::NotFound;
+                            self.label.set_text("Маршрут не найден");
+                        }
+                    }
+                    return;
+                }
+                // поиск в отдельном потоке, в модели остаётся копия карты,
+                // на которой показываются полученные события
+                if self.background_check.get_active() {
+                    let index = self.combo_box.get_active() as usize;
+                    if let Some(entry) = self.model.registry.entries().get(index) {
+                        let map = self.model.search.map.clone();
+                        let mut search = ShortestPath::new(map, entry.create());
+                        search.set_goal_mode(self.goal_mode());
+                        let search = mem::replace(&mut self.model.search, search);
+                        self.model.worker = Some(SearchWorker::spawn(search));
+                        self.model.skip_animation = false;
+                        self.skip_button.set_sensitive(true);
+                        self.model.status = SearchStatus::Searching;
+                        return;
+                    }
+                }
+                // инициализация поиска
+                self.model.search.init();
                 // сообщения Msg::Next будут обрабатываться в соотвествии subscriptions
                 self.model.status = SearchStatus::Searching;
             }
+            Msg::GoalModeChange => {
+                let goal_mode = self.goal_mode();
+                self.model.search.set_goal_mode(goal_mode);
+                self.replan(false);
+            }
+            Msg::Import => {
+                let file_chooser = gtk::FileChooserNative::new(
+                    Some("Импорт карты из изображения"),
+                    Some(&self.window),
+                    gtk::FileChooserAction::Open,
+                    Some("Открыть"),
+                    Some("Отменить"),
+                );
+                let filter = gtk::FileFilter::new();
+                filter.set_name("Изображения");
+                filter.add_pattern("*.png");
+                filter.add_pattern("*.bmp");
+                file_chooser.add_filter(&filter);
+                if file_chooser.run() == gtk::ResponseType::Accept.into() {
+                    let path = file_chooser.get_filename().unwrap();
+                    if let Some(options) = self.import_options() {
+                        let map = try_message!(import::import(&path, &options));
+                        self.model.search.map.replace_from(&map);
+                        self.show_topology();
+                        self.reset_world();
+                        self.replan(true);
+                        self.success_message("Карта импортирована");
+                    }
+                }
+            }
             Msg::MoveCursor(pos) => {
                 self.model.cursor.position = pos;
                 if self.model.cursor.button_pressed {
                     let pos = self.get_cursor_pos();
-                    self.model.search.map.set_cell(self.model.cursor.cell, pos);
+                    match (self.model.cursor.cell, self.model.cursor.drag) {
+                        // перетаскивание одной из конечных клеток
+                        (Cell::Finish, Some(from)) => {
+                            if self.model.search.map.move_finish(from, pos) {
+                                self.model.cursor.drag = Some(pos);
+                            }
+                        }
+                        // перетаскивание промежуточной клетки
+                        (Cell::Waypoint, Some(from)) => {
+                            if self.model.search.map.move_waypoint(from, pos) {
+                                self.model.cursor.drag = Some(pos);
+                            }
+                        }
+                        (cell, _) => self.model.search.map.set_cell(cell, pos),
+                    }
+                    let cell = self.model.cursor.cell;
+                    self.replan(cell == Cell::Passable || cell == Cell::Impassable);
+                }
+            }
+            // новый слой того же размера и формы клеток над последним
+            Msg::LayerAdd => {
+                self.update(Msg::ClearPath);
+                self.store_layer();
+                let map = &self.model.search.map;
+                let mut layer = try_message!(Map::new(map.rows(), map.cols()));
+                layer.set_topology(map.topology());
+                layer.set_wrap(map.wrap());
+                self.model.search.map.replace_from(&layer);
+                self.model.layer = self.model.world.add_layer(layer);
+                self.show_layers();
+                self.replan(true);
+            }
+            // путь по слоям остаётся показанным на выбранном слое
+            Msg::LayerChange => {
+                let index = self.layer_combo.get_active();
+                if index < 0 || index as usize == self.model.layer {
+                    return;
+                }
+                let world_path = self.model.world_path.take();
+                self.update(Msg::ClearPath);
+                self.store_layer();
+                self.model.layer = index as usize;
+                if let Some(map) = self.model.world.layer(self.model.layer) {
+                    self.model.search.map.replace_from(map);
+                }
+                self.model.flow = None;
+                self.show_topology();
+                if let Some(path) = world_path {
+                    self.model.paths = path.segments(self.model.layer);
+                    self.model.world_path = Some(path);
+                }
+            }
+            // удаление отображаемого слоя вместе с его переходами
+            Msg::LayerRemove => {
+                self.update(Msg::ClearPath);
+                if self.model.world.remove_layer(self.model.layer).is_none() {
+                    Win::error_message("Единственный слой карты нельзя удалить");
+                    return;
+                }
+                self.model.layer = self.model.layer.saturating_sub(1);
+                if let Some(map) = self.model.world.layer(self.model.layer) {
+                    self.model.search.map.replace_from(map);
+                }
+                self.model.link_start = None;
+                self.show_topology();
+                self.show_layers();
+                self.replan(true);
+            }
+            // в живом режиме путь ищется сразу после изменения карты,
+            // поиск по кнопке не нужен
+            Msg::LiveChange => {
+                self.update(Msg::ClearPath);
+                if self.live_check.get_active() {
+                    self.model.live = Some(LivePlanner::new());
+                    self.search_path_button.set_sensitive(false);
+                    self.replan(true);
+                } else {
+                    self.model.live = None;
+                    self.search_path_button.set_sensitive(true);
+                }
+            }
+            // переключатель также обновляется при загрузке карты,
+            // тогда форма клеток уже совпадает с выбранной
+            Msg::TopologyChange => {
+                let topology = if self.hex_check.get_active() {
+                    Topology::Hex
+                } else {
+                    Topology::Square
+                };
+                if self.model.search.map.topology() != topology {
+                    self.update(Msg::ClearPath);
+                    self.model.search.map.set_topology(topology);
+                    self.replan(true);
+                }
+            }
+            Msg::WrapChange => {
+                let wrap = self.wrap_check.get_active();
+                if self.model.search.map.wrap() != wrap {
+                    self.update(Msg::ClearPath);
+                    self.model.search.map.set_wrap(wrap);
+                    self.replan(true);
                 }
             }
             Msg::Next => {
+                // альтернативные пути, найденные в отдельном потоке
+                let found = self
+                    .model
+                    .alternatives_worker
+                    .as_ref()
+                    .and_then(|worker| worker.try_recv());
+                if let Some(alternatives) = found {
+                    self.model.alternatives_worker = None;
+                    for (i, alternative) in alternatives.iter().enumerate() {
+                        self.alternatives_combo
+                            .append_text(&format!("Путь {}: {:.2}", i + 1, alternative.cost));
+                    }
+                    self.alternatives_combo.set_sensitive(true);
+                    self.model.alternatives = alternatives;
+                }
+                // движение агентов по найденным путям
+                if let Some(ref solution) = self.model.solution {
+                    if self.model.status == SearchStatus::Searching {
+                        self.model.time += AGENT_SPEED;
+                        let makespan = solution.makespan() as f64;
+                        if self.model.time >= makespan {
+                            self.model.time = makespan;
+                            self.model.status = SearchStatus::Found(makespan);
+                        }
+                    }
+                    return;
+                }
+                // воспроизведение записанной трассы
+                if let Some(ref mut replay) = self.model.replay {
+                    if self.model.status == SearchStatus::Searching {
+                        self.model.status = replay.next(&mut self.model.search.map);
+                        match self.model.status {
+                            SearchStatus::Found(len) => {
+                                self.model.paths = replay
+                                    .paths()
+                                    .into_iter()
+                                    .map(|(_, _, path)| path)
+                                    .collect();
+                                self.label.set_text(&format!(
+                                    "Длина пути: {:.2} (трасса, {})",
+                                    len,
+                                    replay.algorithm()
+                                ));
+                            }
+                            SearchStatus::NotFound => {
+                                self.label.set_text("Путь не найден (трасса)");
+                            }
+                            _ => (),
+                        }
+                    }
+                    return;
+                }
+                // поиск в отдельном потоке: события показываются по мере получения,
+                // результат - после показа всех событий
+                if let Some(ref worker) = self.model.worker {
+                    while let Some(message) = worker.try_recv() {
+                        match message {
+                            WorkerMessage::Progress { events, stats } => {
+                                self.model.pending.extend(events);
+                                self.label
+                                    .set_text(&format!("Раскрыто клеток: {}", stats.expanded));
+                            }
+                            WorkerMessage::Finished { search, status } => {
+                                self.model.finished = Some((*search, status));
+                            }
+                        }
+                    }
+                    if self.model.pending.len() > MAX_PENDING_EVENTS {
+                        self.model.skip_animation = true;
+                        self.skip_button.set_sensitive(false);
+                    }
+                    if self.model.skip_animation {
+                        self.model.pending.clear();
+                    }
+                    let count = WORKER_EVENTS
+                        .max(self.model.pending.len() / WORKER_CATCH_UP)
+                        .min(self.model.pending.len());
+                    for event in self.model.pending.drain(..count) {
+                        event.apply(&mut self.model.search.map);
+                    }
+                    if self.model.pending.is_empty() {
+                        if let Some((search, status)) = self.model.finished.take() {
+                            self.model.worker = None;
+                            self.model.search = search;
+                            self.skip_button.set_sensitive(false);
+                            match status {
+                                SearchStatus::Found(len) => self.show_found(len),
+                                status => self.model.status = status,
+                            }
+                        }
+                    }
+                    return;
+                }
                 if self.model.status == SearchStatus::Searching {
                     match self.model.search.next() {
-                        SearchStatus::Found(len) => {
-                            self.model.status = SearchStatus::Found(len);
-                            self.model.path = self.model.search.path();
-                            self.label
-                                .set_text(format!("Длина пути: {:.2}", len).as_str());
-                        }
+                        SearchStatus::Found(len) => self.show_found(len),
                         status => self.model.status = status,
                     }
                 }
@@ -348,16 +1205,68 @@ impl Widget for Win {
                     Some("Открыть"),
                     Some("Отменить"),
                 );
+                Win::add_map_filters(&file_chooser);
                 if file_chooser.run() == gtk::ResponseType::Accept.into() {
-                    let vec = try_message!(fs::read(file_chooser.get_filename().unwrap()));
-                    self.model
-                        .search
-                        .map
-                        .replace_from(&try_message!(deserialize(&vec)));
+                    let map = try_message!(format::load(&file_chooser.get_filename().unwrap()));
+                    self.model.search.map.replace_from(&map);
+                    self.show_topology();
+                    self.reset_world();
+                    self.replan(true);
                     self.success_message("Карта загружена");
                 }
             }
             Msg::Quit => gtk::main_quit(),
+            // поиск выбранным алгоритмом на копии карты с записью трассы
+            Msg::RecordTrace => {
+                let file_chooser = gtk::FileChooserNative::new(
+                    Some("Записать трассу поиска"),
+                    Some(&self.window),
+                    gtk::FileChooserAction::Save,
+                    Some("Сохранить"),
+                    Some("Отменить"),
+                );
+                Win::add_trace_filter(&file_chooser);
+                if file_chooser.run() == gtk::ResponseType::Accept.into() {
+                    let mut path = file_chooser.get_filename().unwrap();
+                    if path.extension().is_none() {
+                        path.set_extension("jsonl");
+                    }
+                    let index = self.combo_box.get_active() as usize;
+                    let entry = match self.model.registry.entries().get(index) {
+                        Some(entry) => entry,
+                        None => return,
+                    };
+                    let map = self.model.search.map.clone();
+                    let mut search = ShortestPath::new(map, entry.create());
+                    search.set_goal_mode(self.goal_mode());
+                    try_message!(trace::record(&mut search, entry.name, &path));
+                    self.success_message("Трасса записана");
+                }
+            }
+            // загрузка карты и трассы, события показываются в сообщениях Msg::Next
+            Msg::ReplayTrace => {
+                let file_chooser = gtk::FileChooserNative::new(
+                    Some("Воспроизвести трассу поиска"),
+                    Some(&self.window),
+                    gtk::FileChooserAction::Open,
+                    Some("Открыть"),
+                    Some("Отменить"),
+                );
+                Win::add_trace_filter(&file_chooser);
+                if file_chooser.run() == gtk::ResponseType::Accept.into() {
+                    let trace = try_message!(Trace::load(&file_chooser.get_filename().unwrap()));
+                    self.model.search.map.replace_from(&trace.map);
+                    self.model.flow = None;
+                    self.show_topology();
+                    self.reset_world();
+                    self.model.replay = Some(Replay::new(trace));
+                    self.search_path_button.hide();
+                    self.clear_path_button.show();
+                    self.set_controls_sensitive(false);
+                    self.model.status = SearchStatus::Searching;
+                    self.label.set_text("Воспроизведение трассы");
+                }
+            }
             Msg::Save => {
                 let file_chooser = gtk::FileChooserNative::new(
                     Some("Сохранить карту"),
@@ -366,73 +1275,48 @@ impl Widget for Win {
                     Some("Сохранить"),
                     Some("Отменить"),
                 );
+                Win::add_map_filters(&file_chooser);
                 if file_chooser.run() == gtk::ResponseType::Accept.into() {
-                    let vec = try_message!(serialize(&self.model.search.map));
-                    try_message!(fs::write(file_chooser.get_filename().unwrap(), vec));
+                    let mut path = file_chooser.get_filename().unwrap();
+                    // без расширения формат определяется выбранным фильтром
+                    if path.extension().is_none() {
+                        let format = file_chooser
+                            .get_filter()
+                            .and_then(|f| f.get_name())
+                            .and_then(|n| MapFormat::from_name(&n))
+                            .unwrap_or(MapFormat::Text);
+                        path.set_extension(format.extension());
+                    }
+                    try_message!(format::save(&path, &self.model.search.map));
                     self.success_message("Карта сохранена");
                 }
             }
+            Msg::SkipAnimation => self.model.skip_animation = true,
+            Msg::SmoothingChange => self.show_smoothed_length(),
             // сообщение отрисовки
             Msg::UpdateDrawBuffer => {
                 // размер карты
                 let allocation = self.drawing_area.get_allocation();
                 // контекст для рисования
                 let context = self.model.draw_handler.get_context();
-                context.rectangle(
-                    0f64,
-                    0f64,
-                    f64::from(allocation.width),
-                    f64::from(allocation.height),
-                );
-                context.set_source_rgb(0.0, 0.0, 0.0);
-                context.fill();
-
-                let cell_width = f64::from(allocation.width) / self.model.search.map.cols() as f64;
-                let cell_height =
-                    f64::from(allocation.height) / self.model.search.map.rows() as f64;
-
-                // отрисовка карты
-                let border = 1f64;
-                for i in 0..self.model.search.map.rows() {
-                    for j in 0..self.model.search.map.cols() {
-                        let color = match self.model.search.map[i][j] {
-                            Cell::Passable => Color::white(),
-                            Cell::Impassable => Color::grey(),
-                            Cell::Start => Color::green(),
-                            Cell::Finish => Color::red(),
-                            Cell::Visited => Color::pale_blue(),
-                            Cell::InQueue => Color::pale_green(),
-                        };
-                        context.set_source_rgb(color.red, color.green, color.blue);
-                        context.rectangle(
-                            j as f64 * cell_width + border,
-                            i as f64 * cell_height + border,
-                            cell_width - 2f64 * border,
-                            cell_height - 2f64 * border,
-                        );
-                        context.fill();
-                    }
-                }
-
-                // отрисовка пути
-                if let Some(ref path) = self.model.path {
-                    if path.len() > 1 {
-                        let color = Color::yellow();
-                        context.set_line_width(3f64);
-                        context.set_source_rgb(color.red, color.green, color.blue);
-                        context.move_to(
-                            (path[0].y as f64 + 0.5f64) * cell_width,
-                            (path[0].x as f64 + 0.5f64) * cell_height,
-                        );
-                        for c in path.iter().skip(1) {
-                            context.line_to(
-                                (c.y as f64 + 0.5f64) * cell_width,
-                                (c.x as f64 + 0.5f64) * cell_height,
-                            );
-                        }
-                        context.stroke();
-                    }
+                // поле направлений строится только после изменения карты
+                if self.flow_check.get_active() && self.model.flow.is_none() {
+                    self.model.flow = Some(FlowField::new(&self.model.search.map));
                 }
+                let flow = self.model.flow.as_ref().filter(|_| self.flow_check.get_active());
+                let (paths, curves) = self.visible_paths();
+                Renderer::new(&self.model.search.map)
+                    .with_links(self.model.world.links(), self.model.layer)
+                    .with_flow(flow)
+                    .with_paths(paths)
+                    .with_curves(&curves)
+                    .with_route(self.model.route.as_ref())
+                    .with_agents(self.model.solution.as_ref(), self.model.time)
+                    .draw(
+                        &context,
+                        f64::from(allocation.width),
+                        f64::from(allocation.height),
+                    );
             }
         }
     }
@@ -485,6 +1369,28 @@ impl Widget for Win {
                     },
                     clicked => Msg::Open,
                 },
+                #[name="import_button"]
+                gtk::Button {
+                    label: "Импорт изображения",
+                    cell: {
+                        left_attach: 4,
+                        top_attach: 19,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::Import,
+                },
+                #[name="export_button"]
+                gtk::Button {
+                    label: "Экспорт изображения",
+                    cell: {
+                        left_attach: 8,
+                        top_attach: 19,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::Export,
+                },
                 #[name="clear_button"]
                 gtk::Button {
                     label: "Очистить карту",
@@ -506,6 +1412,57 @@ impl Widget for Win {
                     },
                     clicked => Msg::About,
                 },
+                #[name="tool_combo"]
+                gtk::ComboBoxText {
+                    cell: {
+                        left_attach: 12,
+                        top_attach: 18,
+                        width: 4,
+                        height: 1,
+                    },
+                },
+                #[name="all_goals_check"]
+                gtk::CheckButton {
+                    label: "Все цели",
+                    cell: {
+                        left_attach: 12,
+                        top_attach: 19,
+                        width: 4,
+                        height: 1,
+                    },
+                    toggled => Msg::GoalModeChange,
+                },
+                #[name="alternatives_combo"]
+                gtk::ComboBoxText {
+                    sensitive: false,
+                    cell: {
+                        left_attach: 8,
+                        top_attach: 18,
+                        width: 4,
+                        height: 1,
+                    },
+                    changed => Msg::AlternativeChange,
+                },
+                #[name="flow_check"]
+                gtk::CheckButton {
+                    label: "Поле направлений",
+                    cell: {
+                        left_attach: 16,
+                        top_attach: 18,
+                        width: 5,
+                        height: 1,
+                    },
+                },
+                #[name="optimize_check"]
+                gtk::CheckButton {
+                    label: "Оптимизировать порядок",
+                    cell: {
+                        left_attach: 16,
+                        top_attach: 19,
+                        width: 5,
+                        height: 1,
+                    },
+                },
                 #[name="combo_box"]
                 gtk::ComboBoxText {
                     cell: {
@@ -539,6 +1496,125 @@ impl Widget for Win {
                     visible: false,
                     clicked => Msg::ClearPath,
                 },
+                #[name="smooth_combo"]
+                gtk::ComboBoxText {
+                    cell: {
+                        left_attach: 0,
+                        top_attach: 20,
+                        width: 4,
+                        height: 1,
+                    },
+                    changed => Msg::SmoothingChange,
+                },
+                #[name="record_trace_button"]
+                gtk::Button {
+                    label: "Записать трассу",
+                    cell: {
+                        left_attach: 4,
+                        top_attach: 20,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::RecordTrace,
+                },
+                #[name="replay_trace_button"]
+                gtk::Button {
+                    label: "Воспроизвести трассу",
+                    cell: {
+                        left_attach: 8,
+                        top_attach: 20,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::ReplayTrace,
+                },
+                #[name="background_check"]
+                gtk::CheckButton {
+                    label: "Фоновый поиск",
+                    cell: {
+                        left_attach: 12,
+                        top_attach: 20,
+                        width: 4,
+                        height: 1,
+                    },
+                },
+                #[name="skip_button"]
+                gtk::Button {
+                    label: "Пропустить анимацию",
+                    sensitive: false,
+                    cell: {
+                        left_attach: 16,
+                        top_attach: 20,
+                        width: 5,
+                        height: 1,
+                    },
+                    clicked => Msg::SkipAnimation,
+                },
+                #[name="live_check"]
+                gtk::CheckButton {
+                    label: "Живой поиск",
+                    cell: {
+                        left_attach: 23,
+                        top_attach: 20,
+                        width: 4,
+                        height: 1,
+                    },
+                    toggled => Msg::LiveChange,
+                },
+                #[name="hex_check"]
+                gtk::CheckButton {
+                    label: "Шестиугольники",
+                    cell: {
+                        left_attach: 27,
+                        top_attach: 20,
+                        width: 5,
+                        height: 1,
+                    },
+                    toggled => Msg::TopologyChange,
+                },
+                #[name="layer_combo"]
+                gtk::ComboBoxText {
+                    cell: {
+                        left_attach: 0,
+                        top_attach: 21,
+                        width: 4,
+                        height: 1,
+                    },
+                    changed => Msg::LayerChange,
+                },
+                #[name="add_layer_button"]
+                gtk::Button {
+                    label: "Добавить слой",
+                    cell: {
+                        left_attach: 4,
+                        top_attach: 21,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::LayerAdd,
+                },
+                #[name="remove_layer_button"]
+                gtk::Button {
+                    label: "Удалить слой",
+                    cell: {
+                        left_attach: 8,
+                        top_attach: 21,
+                        width: 4,
+                        height: 1,
+                    },
+                    clicked => Msg::LayerRemove,
+                },
+                #[name="wrap_check"]
+                gtk::CheckButton {
+                    label: "Замкнуть края",
+                    cell: {
+                        left_attach: 12,
+                        top_attach: 21,
+                        width: 4,
+                        height: 1,
+                    },
+                    toggled => Msg::WrapChange,
+                },
                 #[name="label"]
                 gtk::Label {
                     text: "Длина пути:",
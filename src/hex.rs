@@ -0,0 +1,148 @@
+use map::MapPos;
+
+// шестиугольные клетки с острыми верхушками, нечётные строки сдвинуты вправо
+// на половину клетки: смещённые координаты (строка, столбец) совпадают с MapPos,
+// в осевых координатах (q, r) соседи и расстояния вычисляются одинаково для всех строк
+
+// расстояние между соседними строками при ширине клетки 1
+pub const ROW_HEIGHT: f64 = 0.866_025_403_784_438_6;
+
+// направления к соседям в осевых координатах: восток, северо-восток,
+// северо-запад, запад, юго-запад, юго-восток
+pub const DIRECTIONS: [(isize, isize); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+// осевые координаты клетки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Axial {
+    pub q: isize,
+    pub r: isize,
+}
+
+impl Axial {
+    pub fn new(q: isize, r: isize) -> Self {
+        Self { q, r }
+    }
+
+    pub fn from_offset(pos: MapPos) -> Self {
+        Axial::from_coords(pos.x as isize, pos.y as isize)
+    }
+
+    // смещённые координаты (строка, столбец) могут выходить за пределы карты
+    pub fn from_coords(row: isize, col: isize) -> Self {
+        Axial::new(col - (row - (row & 1)) / 2, row)
+    }
+
+    // смещённые координаты (строка, столбец), могут быть отрицательными
+    pub fn offset(self) -> (isize, isize) {
+        (self.r, self.q + (self.r - (self.r & 1)) / 2)
+    }
+
+    // позиция на карте, None для клеток с отрицательными смещёнными координатами
+    pub fn to_pos(self) -> Option<MapPos> {
+        match self.offset() {
+            (x, y) if x >= 0 && y >= 0 => Some(MapPos::new(x as usize, y as usize)),
+            _ => None,
+        }
+    }
+
+    // число шагов между клетками без учёта стен
+    pub fn distance(self, other: Axial) -> usize {
+        let dq = self.q - other.q;
+        let dr = self.r - other.r;
+        ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as usize
+    }
+
+    pub fn neighbors(self) -> [Axial; 6] {
+        let mut neighbors = [self; 6];
+        for (n, (dq, dr)) in neighbors.iter_mut().zip(DIRECTIONS.iter()) {
+            *n = Axial::new(self.q + dq, self.r + dr);
+        }
+        neighbors
+    }
+
+    // ближайшая клетка к точке с дробными осевыми координатами
+    pub fn round(q: f64, r: f64) -> Self {
+        let s = -q - r;
+        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        }
+        Axial::new(rq as isize, rr as isize)
+    }
+}
+
+// число шагов между клетками карты без учёта стен
+pub fn distance(p: MapPos, q: MapPos) -> usize {
+    Axial::from_offset(p).distance(Axial::from_offset(q))
+}
+
+// центр клетки на плоскости (по вертикали, по горизонтали) при ширине клетки 1,
+// центры соседних клеток находятся на расстоянии 1
+pub fn plane(pos: MapPos) -> (f64, f64) {
+    (
+        pos.x as f64 * ROW_HEIGHT,
+        pos.y as f64 + 0.5f64 * (pos.x & 1) as f64,
+    )
+}
+
+// размер сетки в ширинах и высотах клетки
+pub fn extent(rows: usize, cols: usize) -> (f64, f64) {
+    (cols as f64 + 0.5f64, 0.75f64 * rows as f64 + 0.25f64)
+}
+
+// смещённые координаты клетки под точкой области отрисовки (x - по горизонтали,
+// y - по вертикали) при размере клетки cell_width на cell_height,
+// клетки могут быть растянуты по любой из осей
+pub fn pixel_to_offset(x: f64, y: f64, cell_width: f64, cell_height: f64) -> (isize, isize) {
+    // переход к правильным шестиугольникам с радиусом 1 и центром клетки (0, 0) в начале координат
+    let px = (x - cell_width / 2f64) / (cell_width / 3f64.sqrt());
+    let py = (y - cell_height / 2f64) / (cell_height / 2f64);
+    let q = px / 3f64.sqrt() - py / 3f64;
+    let r = py * 2f64 / 3f64;
+    Axial::round(q, r).offset()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_and_axial_agree() {
+        for x in 0..6 {
+            for y in 0..6 {
+                let pos = MapPos::new(x, y);
+                let axial = Axial::from_offset(pos);
+                assert_eq!(axial.to_pos(), Some(pos));
+                // соседи находятся на расстоянии 1, и их центры тоже
+                for n in axial.neighbors().iter().filter_map(|n| n.to_pos()) {
+                    assert_eq!(distance(pos, n), 1);
+                    let (a, b) = (plane(pos), plane(n));
+                    assert!(((a.0 - b.0).hypot(a.1 - b.1) - 1f64).abs() < 1e-9);
+                }
+            }
+        }
+        assert_eq!(distance(MapPos::new(0, 0), MapPos::new(3, 3)), 5);
+        assert_eq!(distance(MapPos::new(1, 0), MapPos::new(1, 5)), 5);
+    }
+
+    #[test]
+    fn pixel_hits_cell() {
+        let (w, h) = (20f64, 30f64);
+        for x in 0..5 {
+            for y in 0..5 {
+                let pos = MapPos::new(x, y);
+                let (cy, cx) = plane(pos);
+                let (px, py) = ((cx + 0.5f64) * w, (cy / ROW_HEIGHT * 0.75f64 + 0.5f64) * h);
+                assert_eq!(pixel_to_offset(px, py, w, h), (x as isize, y as isize));
+                // точка возле верхней вершины шестиугольника
+                let (ox, oy) = pixel_to_offset(px, py - h * 0.45f64, w, h);
+                assert_eq!((ox as usize, oy as usize), (x, y));
+            }
+        }
+        // левый верхний угол области находится над первой строкой
+        assert_eq!(pixel_to_offset(1f64, 1f64, w, h).0, -1);
+    }
+}
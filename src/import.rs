@@ -0,0 +1,389 @@
+use color::Color;
+use gdk_pixbuf::Pixbuf;
+use glib;
+use map::{Cell, Map, MapError, MapPos, MAX_WEIGHT};
+use std::error::Error;
+use std::f64;
+use std::fmt;
+use std::path::Path;
+
+// клетка карты, в которую преобразуется пиксель
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terrain {
+    Wall,
+    Weight(u8),
+    Start,
+    Finish,
+}
+
+// способ преобразования цвета пикселя в клетку карты
+#[derive(Debug, Clone)]
+pub enum PixelMapping {
+    // пиксели темнее порога становятся стенами, остальные - проходимыми клетками,
+    // вес которых растёт от 1 для белого до max_weight для цвета на пороге,
+    // зелёный и красный пиксели отмечают начальную и конечную клетку
+    Threshold { wall: f64, max_weight: u8 },
+    // пиксель становится клеткой ближайшего цвета палитры
+    Palette(Vec<(Color, Terrain)>),
+}
+
+impl PixelMapping {
+    // палитра с цветами, которыми карта отображается в окне программы
+    pub fn default_palette() -> Self {
+        PixelMapping::Palette(vec![
+            (Color::white(), Terrain::Weight(1)),
+            (Color::brown(), Terrain::Weight(MAX_WEIGHT)),
+            (Color::grey(), Terrain::Wall),
+            (Color::black(), Terrain::Wall),
+            (Color::green(), Terrain::Start),
+            (Color::red(), Terrain::Finish),
+        ])
+    }
+
+    fn terrain(&self, color: &Color) -> Terrain {
+        match self {
+            PixelMapping::Threshold { wall, max_weight } => {
+                if color.distance(&Color::green()) < MARKER_DISTANCE {
+                    Terrain::Start
+                } else if color.distance(&Color::red()) < MARKER_DISTANCE {
+                    Terrain::Finish
+                } else if color.brightness() < *wall {
+                    Terrain::Wall
+                } else {
+                    let t = (1f64 - color.brightness()) / (1f64 - wall).max(f64::EPSILON);
+                    let max_weight = f64::from((*max_weight).clamp(1, MAX_WEIGHT));
+                    Terrain::Weight(1 + (t * (max_weight - 1f64)).round() as u8)
+                }
+            }
+            PixelMapping::Palette(palette) => palette
+                .iter()
+                .min_by(|a, b| {
+                    let a = a.0.distance(color);
+                    let b = b.0.distance(color);
+                    a.partial_cmp(&b).unwrap()
+                })
+                .map(|p| p.1)
+                .unwrap_or(Terrain::Weight(1)),
+        }
+    }
+}
+
+// максимальное расстояние до зелёного или красного цвета,
+// при котором пиксель считается отметкой начальной или конечной клетки
+const MARKER_DISTANCE: f64 = 0.3;
+
+// параметры импорта карты из изображения
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub mapping: PixelMapping,
+    // размер стороны клетки в пикселях
+    pub cell_size: usize,
+    // максимальный размер карты (строки, столбцы),
+    // большие изображения уменьшаются с сохранением пропорций
+    pub max_size: Option<(usize, usize)>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            mapping: PixelMapping::Threshold {
+                wall: 0.5,
+                max_weight: MAX_WEIGHT,
+            },
+            cell_size: 1,
+            max_size: Some((200, 200)),
+        }
+    }
+}
+
+impl ImportOptions {
+    // размер карты (строки, столбцы) для изображения указанного размера
+    fn map_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let cell_size = self.cell_size.max(1);
+        let mut rows = (height / cell_size).max(1);
+        let mut cols = (width / cell_size).max(1);
+        if let Some((max_rows, max_cols)) = self.max_size {
+            let scale = (max_rows as f64 / rows as f64)
+                .min(max_cols as f64 / cols as f64)
+                .min(1f64);
+            rows = ((rows as f64 * scale) as usize).max(1);
+            cols = ((cols as f64 * scale) as usize).max(1);
+        }
+        (rows, cols)
+    }
+}
+
+// ошибка импорта изображения
+#[derive(Debug)]
+pub enum ImportError {
+    Image(glib::Error),
+    Map(MapError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Image(err) => write!(f, "{}", err),
+            ImportError::Map(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<glib::Error> for ImportError {
+    fn from(err: glib::Error) -> Self {
+        ImportError::Image(err)
+    }
+}
+
+impl From<MapError> for ImportError {
+    fn from(err: MapError) -> Self {
+        ImportError::Map(err)
+    }
+}
+
+// импорт карты из файла изображения (PNG, BMP и другие форматы gdk-pixbuf)
+pub fn import(path: &Path, options: &ImportOptions) -> Result<Map, ImportError> {
+    let pixbuf = Pixbuf::new_from_file(path)?;
+    from_pixbuf(&pixbuf, options)
+}
+
+pub fn from_pixbuf(pixbuf: &Pixbuf, options: &ImportOptions) -> Result<Map, ImportError> {
+    let pixels = Pixels {
+        data: unsafe { pixbuf.get_pixels() },
+        width: pixbuf.get_width() as usize,
+        height: pixbuf.get_height() as usize,
+        channels: pixbuf.get_n_channels() as usize,
+        stride: pixbuf.get_rowstride() as usize,
+        alpha: pixbuf.get_has_alpha(),
+    };
+    Ok(from_pixels(&pixels, options)?)
+}
+
+// пиксели изображения по строкам в том виде, в котором их хранит gdk-pixbuf:
+// channels байт на пиксель (RGB или RGBA), stride байт на строку
+pub struct Pixels<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub stride: usize,
+    pub alpha: bool,
+}
+
+impl<'a> Pixels<'a> {
+    fn color(&self, x: usize, y: usize) -> Color {
+        let i = x * self.stride + y * self.channels;
+        // прозрачные пиксели считаются белыми
+        if self.alpha && self.data[i + 3] < 128 {
+            Color::white()
+        } else {
+            Color::from_rgb8(self.data[i], self.data[i + 1], self.data[i + 2])
+        }
+    }
+}
+
+// построение карты по пикселям изображения, клетка получает цвет пикселя
+// в центре соответствующей ей области: ближайший сосед при уменьшении
+// сохраняет чистые цвета отметок и палитры
+pub fn from_pixels(pixels: &Pixels, options: &ImportOptions) -> Result<Map, MapError> {
+    let (rows, cols) = options.map_size(pixels.width, pixels.height);
+    let colors = (0..rows * cols)
+        .map(|k| {
+            let x = (2 * (k / cols) + 1) * pixels.height / (2 * rows);
+            let y = (2 * (k % cols) + 1) * pixels.width / (2 * cols);
+            pixels.color(x, y)
+        })
+        .collect::<Vec<_>>();
+    from_colors(rows, cols, &colors, &options.mapping)
+}
+
+// построение карты по цветам клеток, записанным по строкам
+pub fn from_colors(
+    rows: usize,
+    cols: usize,
+    colors: &[Color],
+    mapping: &PixelMapping,
+) -> Result<Map, MapError> {
+    let terrain = colors
+        .iter()
+        .map(|c| mapping.terrain(c))
+        .collect::<Vec<_>>();
+    let start = marker(cols, &terrain, Terrain::Start);
+    let finish = marker(cols, &terrain, Terrain::Finish);
+    let mut cells = terrain
+        .iter()
+        .map(|t| match t {
+            Terrain::Wall => Cell::Impassable,
+            _ => Cell::Passable,
+        })
+        .collect::<Vec<_>>();
+    // без отметок начальной и конечной клеткой становятся
+    // первая и последняя проходимые клетки
+    let start = start.or_else(|| cells.iter().position(|c| *c == Cell::Passable));
+    if let Some(k) = start {
+        cells[k] = Cell::Start;
+    }
+    let finish = finish.or_else(|| cells.iter().rposition(|c| *c == Cell::Passable));
+    if let Some(k) = finish {
+        cells[k] = Cell::Finish;
+    }
+    let mut map = Map::from_cells(rows, cols, &cells)?;
+    for (k, t) in terrain.iter().enumerate() {
+        if let Terrain::Weight(weight) = t {
+            map.set_weight(MapPos::new(k / cols, k % cols), *weight);
+        }
+    }
+    Ok(map)
+}
+
+// клетка отметки, ближайшая к центру всех клеток с этой отметкой
+fn marker(cols: usize, terrain: &[Terrain], marker: Terrain) -> Option<usize> {
+    let cells = terrain
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| **t == marker)
+        .map(|(k, _)| k)
+        .collect::<Vec<_>>();
+    if cells.is_empty() {
+        return None;
+    }
+    let n = cells.len() as f64;
+    let x = cells.iter().map(|k| (k / cols) as f64).sum::<f64>() / n;
+    let y = cells.iter().map(|k| (k % cols) as f64).sum::<f64>() / n;
+    let distance = |k: usize| ((k / cols) as f64 - x).powi(2) + ((k % cols) as f64 - y).powi(2);
+    cells
+        .into_iter()
+        .min_by(|a, b| distance(*a).partial_cmp(&distance(*b)).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    // изображение RGBA с выравниванием строк, как у gdk-pixbuf
+    fn image<F: Fn(usize, usize) -> [u8; 4]>(height: usize, width: usize, pixel: F) -> Vec<u8> {
+        let stride = width * 4 + 3;
+        let mut data = vec![0; height * stride];
+        for x in 0..height {
+            for y in 0..width {
+                let i = x * stride + y * 4;
+                data[i..i + 4].copy_from_slice(&pixel(x, y));
+            }
+        }
+        data
+    }
+
+    fn import(data: &[u8], height: usize, width: usize, options: &ImportOptions) -> Map {
+        let pixels = Pixels {
+            data,
+            width,
+            height,
+            channels: 4,
+            stride: width * 4 + 3,
+            alpha: true,
+        };
+        from_pixels(&pixels, options).unwrap()
+    }
+
+    fn rows(map: &Map) -> Vec<String> {
+        (0..map.rows())
+            .map(|i| {
+                (0..map.cols())
+                    .map(|j| match map[i][j] {
+                        Cell::Impassable => '#',
+                        Cell::Start => 'S',
+                        Cell::Finish => 'F',
+                        _ => (b'0' + map.weight(MapPos::new(i, j))) as char,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_maps_brightness_to_weights() {
+        let mapping = ImportOptions::default().mapping;
+        let terrain = |r, g, b| mapping.terrain(&Color::from_rgb8(r, g, b));
+        assert_eq!(terrain(255, 255, 255), Terrain::Weight(1));
+        assert_eq!(terrain(191, 191, 191), Terrain::Weight(5));
+        assert_eq!(terrain(128, 128, 128), Terrain::Weight(MAX_WEIGHT));
+        assert_eq!(terrain(100, 100, 100), Terrain::Wall);
+        assert_eq!(terrain(0, 0, 0), Terrain::Wall);
+        assert_eq!(terrain(20, 240, 20), Terrain::Start);
+        assert_eq!(terrain(240, 20, 20), Terrain::Finish);
+        let capped = PixelMapping::Threshold {
+            wall: 0.5,
+            max_weight: 3,
+        };
+        assert_eq!(
+            capped.terrain(&Color::from_rgb8(128, 128, 128)),
+            Terrain::Weight(3)
+        );
+    }
+
+    #[test]
+    fn palette_picks_nearest_color() {
+        let mapping = PixelMapping::default_palette();
+        let terrain = |r, g, b| mapping.terrain(&Color::from_rgb8(r, g, b));
+        assert_eq!(terrain(250, 250, 250), Terrain::Weight(1));
+        assert_eq!(terrain(140, 70, 20), Terrain::Weight(MAX_WEIGHT));
+        assert_eq!(terrain(120, 130, 125), Terrain::Wall);
+        assert_eq!(terrain(10, 10, 10), Terrain::Wall);
+        assert_eq!(terrain(10, 200, 10), Terrain::Start);
+        assert_eq!(terrain(200, 10, 10), Terrain::Finish);
+    }
+
+    #[test]
+    fn markers_and_fallback() {
+        let options = ImportOptions::default();
+        // без отметок начальная и конечная клетки - первая и последняя проходимые
+        let data = image(2, 3, |x, y| if x == y { BLACK } else { WHITE });
+        assert_eq!(rows(&import(&data, 2, 3, &options)), vec!["#S1", "1#F"]);
+        // из нескольких отмеченных пикселей выбирается ближайший к их центру,
+        // прозрачные пиксели считаются белыми
+        let data = image(3, 3, |x, y| match (x, y) {
+            (0, _) => GREEN,
+            (2, 2) => RED,
+            (1, 1) => [0, 0, 0, 0],
+            _ => WHITE,
+        });
+        assert_eq!(
+            rows(&import(&data, 3, 3, &options)),
+            vec!["1S1", "111", "11F"]
+        );
+    }
+
+    #[test]
+    fn large_images_are_reduced() {
+        // блоки 2x2 пикселей одного цвета
+        let block = |x: usize, y: usize| match (x / 2, y / 2) {
+            (0, 0) => GREEN,
+            (1, 3) => RED,
+            (0, 2) | (1, 1) => BLACK,
+            _ => WHITE,
+        };
+        let data = image(4, 8, block);
+        let expected = vec!["S1#1", "1#1F"];
+        let by_cell = ImportOptions {
+            cell_size: 2,
+            max_size: None,
+            ..ImportOptions::default()
+        };
+        assert_eq!(rows(&import(&data, 4, 8, &by_cell)), expected);
+        let by_limit = ImportOptions {
+            max_size: Some((2, 6)),
+            ..ImportOptions::default()
+        };
+        assert_eq!(rows(&import(&data, 4, 8, &by_limit)), expected);
+        assert_eq!(by_limit.map_size(800, 400), (2, 4));
+        assert_eq!(by_limit.map_size(8, 4), (2, 4));
+        assert_eq!(by_cell.map_size(1, 1), (1, 1));
+    }
+}
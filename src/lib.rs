@@ -0,0 +1,41 @@
+extern crate bincode;
+extern crate cairo;
+extern crate gdk;
+extern crate gdk_pixbuf;
+extern crate gif;
+extern crate glib;
+extern crate gtk;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
+#[macro_use]
+extern crate relm;
+extern crate relm_attributes;
+#[macro_use]
+extern crate relm_derive;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod alternatives;
+pub mod animation;
+pub mod bench;
+pub mod cli;
+pub mod color;
+pub mod flow;
+pub mod format;
+pub mod gui;
+pub mod hex;
+pub mod import;
+pub mod live;
+pub mod map;
+pub mod mapf;
+pub mod query;
+pub mod render;
+pub mod route;
+pub mod search;
+pub mod smooth;
+pub mod trace;
+pub mod worker;
+pub mod world;
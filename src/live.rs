@@ -0,0 +1,165 @@
+use flow::FlowField;
+use map::{GoalMode, Map, MapPos};
+use std::cmp::Ordering;
+
+// поиск пути, повторяемый после каждого изменения карты: деревья кратчайших
+// путей сохраняются и используются повторно, пока не изменились стены и веса
+// клеток, поэтому перемещение начальной клетки не требует нового поиска,
+// а при перемещении конечных клеток используется дерево от начальной клетки
+pub struct LivePlanner {
+    // дерево путей из начальной клетки
+    from_start: Option<(MapPos, FlowField)>,
+    // дерево путей к конечным клеткам, для которых оно построено
+    to_goals: Option<(Vec<MapPos>, FlowField)>,
+    // число построенных деревьев
+    builds: usize,
+}
+
+impl LivePlanner {
+    pub fn new() -> Self {
+        Self {
+            from_start: None,
+            to_goals: None,
+            builds: 0,
+        }
+    }
+
+    // изменились стены или веса клеток, сохранённые деревья больше не верны
+    pub fn invalidate(&mut self) {
+        self.from_start = None;
+        self.to_goals = None;
+    }
+
+    pub fn builds(&self) -> usize {
+        self.builds
+    }
+
+    // пути до конечных клеток от ближайшей к дальней, в режиме ближайшей цели
+    // только путь до неё, путь записывается от конца к началу
+    pub fn plan(&mut self, map: &Map, goal_mode: GoalMode) -> Vec<(MapPos, f64, Vec<MapPos>)> {
+        let start = map.start();
+        let finishes = map.finishes();
+        if self.from_start.as_ref().map(|t| t.0) != Some(start) {
+            self.from_start = None;
+        }
+        // конечные клетки перемещены, дальше выгоднее дерево от начальной клетки
+        let goals_moved =
+            matches!(self.to_goals, Some((ref goals, _)) if goals.as_slice() != finishes);
+        if goals_moved {
+            self.to_goals = None;
+        }
+        if goal_mode == GoalMode::Nearest
+            && (self.to_goals.is_some() || (self.from_start.is_none() && !goals_moved))
+        {
+            if self.to_goals.is_none() {
+                self.to_goals = Some((finishes.to_vec(), FlowField::from_goals(map, finishes)));
+                self.builds += 1;
+            }
+            let field = &self.to_goals.as_ref().unwrap().1;
+            return match field.path(start) {
+                Some(mut path) => {
+                    path.reverse();
+                    vec![(path[0], field.distance(start), path)]
+                }
+                None => Vec::new(),
+            };
+        }
+        if self.from_start.is_none() {
+            self.from_start = Some((start, FlowField::from_sources(map, &[start])));
+            self.builds += 1;
+        }
+        let field = &self.from_start.as_ref().unwrap().1;
+        let mut paths = finishes
+            .iter()
+            .filter_map(|f| field.path(*f).map(|path| (*f, field.distance(*f), path)))
+            .collect::<Vec<_>>();
+        paths.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        if goal_mode == GoalMode::Nearest {
+            paths.truncate(1);
+        }
+        paths
+    }
+}
+
+impl Default for LivePlanner {
+    fn default() -> Self {
+        LivePlanner::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{Cell, Direction, Passage, PassageKind, SearchStatus, ShortestPath, UPHILL_FACTOR};
+    use search::BestFirst;
+
+    fn cost(map: &Map) -> Option<f64> {
+        match ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra())).run() {
+            SearchStatus::Found(cost) => Some(cost),
+            _ => None,
+        }
+    }
+
+    // найденный путь совпадает по стоимости с поиском на карте
+    fn check(map: &Map, planner: &mut LivePlanner) {
+        let paths = planner.plan(map, GoalMode::Nearest);
+        assert!((paths[0].1 - cost(map).unwrap()).abs() < 1e-9);
+        assert_eq!(paths[0].2[paths[0].2.len() - 1], map.start());
+        assert_eq!(paths[0].2[0], map.finishes()[0]);
+    }
+
+    #[test]
+    fn dragging_reuses_trees() {
+        let mut map = format::from_text("S...#...\n.##.#.#.\n...#..#F\n.#......\n").unwrap();
+        let mut planner = LivePlanner::new();
+        check(&map, &mut planner);
+        // перемещение начальной клетки использует дерево к конечной клетке
+        for &(x, y) in &[(1, 0), (2, 0), (3, 0), (2, 2)] {
+            map.set_cell(Cell::Start, MapPos::new(x, y));
+            check(&map, &mut planner);
+        }
+        assert_eq!(planner.builds(), 1);
+        // перемещение конечной клетки строит одно дерево от начальной клетки
+        let mut from = map.finishes()[0];
+        for &(x, y) in &[(3, 7), (3, 6), (0, 7)] {
+            assert!(map.move_finish(from, MapPos::new(x, y)));
+            from = MapPos::new(x, y);
+            check(&map, &mut planner);
+        }
+        assert_eq!(planner.builds(), 2);
+        // стены меняют карту, деревья строятся заново
+        map.set_cell(Cell::Impassable, MapPos::new(3, 5));
+        planner.invalidate();
+        check(&map, &mut planner);
+        assert_eq!(planner.builds(), 3);
+    }
+
+    #[test]
+    fn all_goals_and_unreachable() {
+        let mut map = format::from_text("F.S.F\n").unwrap();
+        let mut planner = LivePlanner::new();
+        let paths = planner.plan(&map, GoalMode::All);
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|p| p.1 == 2f64));
+        map.set_cell(Cell::Impassable, MapPos::new(0, 1));
+        map.set_cell(Cell::Impassable, MapPos::new(0, 3));
+        planner.invalidate();
+        assert!(planner.plan(&map, GoalMode::Nearest).is_empty());
+        assert!(planner.plan(&map, GoalMode::All).is_empty());
+    }
+
+    #[test]
+    fn asymmetric_costs() {
+        // склон спускается к начальной клетке, путь к конечной идёт в гору
+        let mut map = format::from_text("S.F\n").unwrap();
+        let slope = Passage::new(PassageKind::Slope, Direction::W);
+        map.set_passage(MapPos::new(0, 1), Some(slope)).unwrap();
+        let expected = cost(&map).unwrap();
+        assert_eq!(expected, 2f64 * UPHILL_FACTOR);
+        for &goal_mode in &[GoalMode::Nearest, GoalMode::All] {
+            let paths = LivePlanner::new().plan(&map, goal_mode);
+            assert!((paths[0].1 - expected).abs() < 1e-9);
+        }
+    }
+}
@@ -1,22 +1,22 @@
 #![windows_subsystem = "windows"]
 
-extern crate bincode;
-extern crate gdk;
-extern crate gtk;
-#[macro_use]
 extern crate relm;
-extern crate relm_attributes;
-#[macro_use]
-extern crate relm_derive;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
+extern crate shortest_path;
 
 use relm::Widget;
-
-mod gui;
-mod map;
+use shortest_path::search::Registry;
+use shortest_path::{cli, gui};
+use std::env;
+use std::process;
 
 fn main() {
-    gui::Win::run((18, 32)).expect("Win::run failed");
+    // собственные алгоритмы добавляются в реестр через Registry::register
+    let registry = Registry::default();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() {
+        gui::Win::run((18, 32, registry)).expect("Win::run failed");
+    } else if let Err(err) = cli::run(&args, &registry) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
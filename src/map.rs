@@ -1,7 +1,12 @@
+use hex;
+use search::{SearchAlgorithm, SearchBudget, SearchEvent, SearchStats};
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
 use std::ops::Index;
 use std::ops::IndexMut;
+use std::time::Instant;
 use std::vec::IntoIter;
 
 // состояния клетки на карте
@@ -13,6 +18,7 @@ pub enum Cell {
     Finish,
     Visited,
     InQueue,
+    Waypoint,
 }
 
 // позиция на карте
@@ -28,15 +34,469 @@ impl MapPos {
     }
 }
 
+// агент с собственными начальной и целевой клетками
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Agent {
+    pub start: MapPos,
+    pub goal: MapPos,
+}
+
+impl Agent {
+    pub fn new(start: MapPos, goal: MapPos) -> Self {
+        Self { start, goal }
+    }
+}
+
+// ошибка проверки карты
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    InvalidSize { rows: usize, cols: usize },
+    DataLength { expected: usize, found: usize },
+    Border(MapPos),
+    StartCount(usize),
+    FinishCount(usize),
+    StartPosition(MapPos),
+    FinishPosition(MapPos),
+    Weight(MapPos),
+    WaypointCount(usize),
+    WaypointPosition(MapPos),
+    Agent(MapPos),
+    OutOfBounds(MapPos),
+    Passage(MapPos),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::InvalidSize { rows, cols } => {
+                write!(f, "Недопустимый размер карты {}x{}", rows, cols)
+            }
+            MapError::DataLength { expected, found } => {
+                write!(f, "Карта содержит {} клеток, ожидалось {}", found, expected)
+            }
+            MapError::Border(pos) => write!(
+                f,
+                "Нарушена стена по периметру карты в клетке ({}, {})",
+                pos.x, pos.y
+            ),
+            MapError::StartCount(n) => write!(
+                f,
+                "Карта должна содержать одну начальную клетку, найдено {}",
+                n
+            ),
+            MapError::FinishCount(n) => write!(
+                f,
+                "Число конечных клеток карты не совпадает с числом целей: {}",
+                n
+            ),
+            MapError::StartPosition(pos) => write!(
+                f,
+                "Начальная позиция ({}, {}) не совпадает с начальной клеткой",
+                pos.x, pos.y
+            ),
+            MapError::FinishPosition(pos) => write!(
+                f,
+                "Конечная позиция ({}, {}) не совпадает с конечной клеткой",
+                pos.x, pos.y
+            ),
+            MapError::Weight(pos) => write!(f, "Недопустимый вес клетки ({}, {})", pos.x, pos.y),
+            MapError::WaypointCount(n) => write!(
+                f,
+                "Число промежуточных клеток карты не совпадает с числом точек маршрута: {}",
+                n
+            ),
+            MapError::WaypointPosition(pos) => write!(
+                f,
+                "Точка маршрута ({}, {}) не совпадает с промежуточной клеткой",
+                pos.x, pos.y
+            ),
+            MapError::Agent(pos) => write!(
+                f,
+                "Недопустимая начальная или целевая клетка агента ({}, {})",
+                pos.x, pos.y
+            ),
+            MapError::Passage(pos) => {
+                write!(f, "Недопустимое направление клетки ({}, {})", pos.x, pos.y)
+            }
+            MapError::OutOfBounds(pos) => {
+                write!(
+                    f,
+                    "Клетка ({}, {}) находится за пределами карты",
+                    pos.x, pos.y
+                )
+            }
+        }
+    }
+}
+
+impl Error for MapError {}
+
+// максимальный вес клетки, вес определяет стоимость прохода через клетку
+pub const MAX_WEIGHT: u8 = 9;
+
+// наибольшее число клеток карты вместе со стеной по периметру (например,
+// 4094x4094), карты большего размера не создаются, чтобы их выделение
+// и рабочие массивы поиска не исчерпали память
+pub const MAX_CELLS: usize = 1 << 24;
+
+// форма клеток карты
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Topology {
+    // квадратные клетки, переходы к восьми соседям
+    #[default]
+    Square,
+    // шестиугольные клетки, переходы к шести соседям, см. модуль hex
+    Hex,
+}
+
+impl Topology {
+    // короткое имя в текстовом формате карты
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "square" => Some(Topology::Square),
+            "hex" => Some(Topology::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Topology::Square => "square",
+            Topology::Hex => "hex",
+        }
+    }
+
+    pub fn is_square(&self) -> bool {
+        *self == Topology::Square
+    }
+}
+
+// направление клетки по сторонам света, север - вверх по строкам
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    N,
+    NE,
+    E,
+    SE,
+    S,
+    SW,
+    W,
+    NW,
+}
+
+impl Direction {
+    // по часовой стрелке, начиная с севера
+    pub const ALL: [Direction; 8] = [
+        Direction::N,
+        Direction::NE,
+        Direction::E,
+        Direction::SE,
+        Direction::S,
+        Direction::SW,
+        Direction::W,
+        Direction::NW,
+    ];
+
+    // вектор направления (по строкам, по столбцам)
+    pub fn vector(self) -> (f64, f64) {
+        match self {
+            Direction::N => (-1f64, 0f64),
+            Direction::NE => (-1f64, 1f64),
+            Direction::E => (0f64, 1f64),
+            Direction::SE => (1f64, 1f64),
+            Direction::S => (1f64, 0f64),
+            Direction::SW => (1f64, -1f64),
+            Direction::W => (0f64, -1f64),
+            Direction::NW => (-1f64, -1f64),
+        }
+    }
+
+    // у шестиугольных клеток нет соседей строго на севере и юге
+    pub fn fits(self, topology: Topology) -> bool {
+        topology.is_square() || (self != Direction::N && self != Direction::S)
+    }
+
+    // короткое имя в текстовом формате карты
+    pub fn from_name(name: &str) -> Option<Self> {
+        Direction::ALL.iter().cloned().find(|d| d.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::N => "n",
+            Direction::NE => "ne",
+            Direction::E => "e",
+            Direction::SE => "se",
+            Direction::S => "s",
+            Direction::SW => "sw",
+            Direction::W => "w",
+            Direction::NW => "nw",
+        }
+    }
+}
+
+// во сколько раз подъём по склону дороже спуска
+pub const UPHILL_FACTOR: f64 = 2.0;
+
+// вид клетки с направлением
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PassageKind {
+    // конвейер: из клетки можно выйти только по направлению
+    Conveyor,
+    // пандус: в клетку можно войти только по направлению
+    Ramp,
+    // склон, спускающийся по направлению: подъём дороже в UPHILL_FACTOR раз
+    Slope,
+}
+
+impl PassageKind {
+    pub const ALL: [PassageKind; 3] =
+        [PassageKind::Conveyor, PassageKind::Ramp, PassageKind::Slope];
+
+    // короткое имя в текстовом формате карты
+    pub fn from_name(name: &str) -> Option<Self> {
+        PassageKind::ALL.iter().cloned().find(|k| k.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PassageKind::Conveyor => "conveyor",
+            PassageKind::Ramp => "ramp",
+            PassageKind::Slope => "slope",
+        }
+    }
+}
+
+// направленная клетка: переходы через неё зависят от их направления
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Passage {
+    pub kind: PassageKind,
+    pub direction: Direction,
+}
+
+impl Passage {
+    pub fn new(kind: PassageKind, direction: Direction) -> Self {
+        Self { kind, direction }
+    }
+}
+
 // карта
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Map {
     cols: usize,
     data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+    // промежуточные клетки в порядке посещения
+    waypoints: Vec<MapPos>,
+    // агенты для многоагентного планирования
+    agents: Vec<Agent>,
+    topology: Topology,
+    // края карты соединены с противоположными
+    wrap: bool,
+    // направленные клетки, индексы те же, что у весов
+    passages: Vec<Option<Passage>>,
+}
+
+// карта версии 1 без весов клеток, используется для чтения старых файлов
+#[derive(Deserialize)]
+pub struct MapV1 {
+    cols: usize,
+    data: Vec<Cell>,
+    start: MapPos,
+    finish: MapPos,
+}
+
+impl From<MapV1> for Map {
+    fn from(map: MapV1) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            weights: vec![1; map.data.len()],
+            data: map.data,
+            start: map.start,
+            finishes: vec![map.finish],
+            waypoints: Vec::new(),
+            agents: Vec::new(),
+            topology: Topology::Square,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 2 с единственной конечной клеткой
+#[derive(Deserialize)]
+pub struct MapV2 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
     start: MapPos,
     finish: MapPos,
 }
 
+impl From<MapV2> for Map {
+    fn from(map: MapV2) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: vec![map.finish],
+            waypoints: Vec::new(),
+            agents: Vec::new(),
+            topology: Topology::Square,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 3 без промежуточных клеток
+#[derive(Deserialize)]
+pub struct MapV3 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+}
+
+impl From<MapV3> for Map {
+    fn from(map: MapV3) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: map.finishes,
+            waypoints: Vec::new(),
+            agents: Vec::new(),
+            topology: Topology::Square,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 4 без агентов
+#[derive(Deserialize)]
+pub struct MapV4 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+    waypoints: Vec<MapPos>,
+}
+
+impl From<MapV4> for Map {
+    fn from(map: MapV4) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: map.finishes,
+            waypoints: map.waypoints,
+            agents: Vec::new(),
+            topology: Topology::Square,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 5 с квадратными клетками
+#[derive(Deserialize)]
+pub struct MapV5 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+    waypoints: Vec<MapPos>,
+    agents: Vec<Agent>,
+}
+
+impl From<MapV5> for Map {
+    fn from(map: MapV5) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: map.finishes,
+            waypoints: map.waypoints,
+            agents: map.agents,
+            topology: Topology::Square,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 6 без соединения краёв
+#[derive(Deserialize)]
+pub struct MapV6 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+    waypoints: Vec<MapPos>,
+    agents: Vec<Agent>,
+    topology: Topology,
+}
+
+impl From<MapV6> for Map {
+    fn from(map: MapV6) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: map.finishes,
+            waypoints: map.waypoints,
+            agents: map.agents,
+            topology: map.topology,
+            wrap: false,
+        }
+    }
+}
+
+// карта версии 7 без направленных клеток
+#[derive(Deserialize)]
+pub struct MapV7 {
+    cols: usize,
+    data: Vec<Cell>,
+    weights: Vec<u8>,
+    start: MapPos,
+    finishes: Vec<MapPos>,
+    waypoints: Vec<MapPos>,
+    agents: Vec<Agent>,
+    topology: Topology,
+    wrap: bool,
+}
+
+impl From<MapV7> for Map {
+    fn from(map: MapV7) -> Self {
+        Map {
+            cols: map.cols,
+            passages: vec![None; map.data.len()],
+            data: map.data,
+            weights: map.weights,
+            start: map.start,
+            finishes: map.finishes,
+            waypoints: map.waypoints,
+            agents: map.agents,
+            topology: map.topology,
+            wrap: map.wrap,
+        }
+    }
+}
+
 impl Index<usize> for Map {
     type Output = [Cell];
 
@@ -56,7 +516,15 @@ impl IndexMut<usize> for Map {
 }
 
 impl Map {
-    pub fn new(rows: usize, cols: usize) -> Self {
+    // карта без стен, начальная клетка в левом верхнем углу, конечная - в правом нижнем,
+    // карта должна вмещать обе клетки и содержать не больше MAX_CELLS клеток
+    pub fn new(rows: usize, cols: usize) -> Result<Self, MapError> {
+        let len = rows
+            .checked_add(2)
+            .and_then(|r| cols.checked_add(2).and_then(|c| r.checked_mul(c)));
+        if rows == 0 || cols == 0 || (rows == 1 && cols == 1) || len.is_none_or(|l| l > MAX_CELLS) {
+            return Err(MapError::InvalidSize { rows, cols });
+        }
         //Увеличиваем рамер карты, для того чтобы сделать стену по периметру,
         //это позволит не проверять границы при поиске соседей.
         let start = MapPos::new(0, 0);
@@ -64,11 +532,18 @@ impl Map {
         let cols = cols + 2;
         let rows = rows + 2;
         let data = vec![Cell::Passable; cols * rows];
+        let weights = vec![1; cols * rows];
         let mut map = Map {
             cols,
             data,
+            weights,
+            passages: vec![None; cols * rows],
             start,
-            finish,
+            finishes: vec![finish],
+            waypoints: Vec::new(),
+            agents: Vec::new(),
+            topology: Topology::Square,
+            wrap: false,
         };
         //Стена слева и справа
         for i in 0..rows {
@@ -82,7 +557,146 @@ impl Map {
         }
         map[start.x][start.y] = Cell::Start;
         map[finish.x][finish.y] = Cell::Finish;
-        map
+        Ok(map)
+    }
+
+    // создание карты из клеток без стены по периметру,
+    // карта должна содержать ровно одну начальную и хотя бы одну конечную клетку,
+    // промежуточные клетки посещаются в порядке обхода по строкам
+    pub fn from_cells(rows: usize, cols: usize, cells: &[Cell]) -> Result<Self, MapError> {
+        // размер проверяется до умножения, которое могло бы переполниться
+        let mut map = Map::new(rows, cols)?;
+        if cells.len() != rows * cols {
+            return Err(MapError::DataLength {
+                expected: rows * cols,
+                found: cells.len(),
+            });
+        }
+        map.finishes.clear();
+        for i in 0..rows {
+            for j in 0..cols {
+                let cell = match cells[i * cols + j] {
+                    Cell::Start => {
+                        map.start = MapPos::new(i, j);
+                        Cell::Start
+                    }
+                    Cell::Finish => {
+                        map.finishes.push(MapPos::new(i, j));
+                        Cell::Finish
+                    }
+                    Cell::Waypoint => {
+                        map.waypoints.push(MapPos::new(i, j));
+                        Cell::Waypoint
+                    }
+                    Cell::Impassable => Cell::Impassable,
+                    _ => Cell::Passable,
+                };
+                map[i][j] = cell;
+            }
+        }
+        map.validate()?;
+        Ok(map)
+    }
+
+    // проверка целостности карты: размеров, стены по периметру,
+    // единственности начальной клетки и соответствия конечных и промежуточных
+    // клеток их спискам
+    pub fn validate(&self) -> Result<(), MapError> {
+        if self.cols < 3 || self.data.len() / self.cols < 3 {
+            return Err(MapError::InvalidSize {
+                rows: (self.data.len() / self.cols.max(1)).saturating_sub(2),
+                cols: self.cols.saturating_sub(2),
+            });
+        }
+        let rows = self.data.len() / self.cols;
+        if rows * self.cols != self.data.len() {
+            return Err(MapError::DataLength {
+                expected: (rows + 1) * self.cols,
+                found: self.data.len(),
+            });
+        }
+        if self.weights.len() != self.data.len() {
+            return Err(MapError::DataLength {
+                expected: self.data.len(),
+                found: self.weights.len(),
+            });
+        }
+        if self.passages.len() != self.data.len() {
+            return Err(MapError::DataLength {
+                expected: self.data.len(),
+                found: self.passages.len(),
+            });
+        }
+        for i in 0..rows {
+            for j in 0..self.cols {
+                let border = i == 0 || j == 0 || i == rows - 1 || j == self.cols - 1;
+                if border && self.get(i, j) != Cell::Impassable {
+                    return Err(MapError::Border(MapPos::new(i, j)));
+                }
+                let weight = self.weights[i * self.cols + j];
+                if weight == 0 || weight > MAX_WEIGHT {
+                    return Err(MapError::Weight(MapPos::new(i, j)));
+                }
+                // направления бывают только у проходимых клеток внутри карты
+                if let Some(passage) = self.passages[i * self.cols + j] {
+                    if border
+                        || self.get(i, j) == Cell::Impassable
+                        || !passage.direction.fits(self.topology)
+                    {
+                        return Err(MapError::Passage(MapPos::new(i, j)));
+                    }
+                }
+            }
+        }
+        let count = |cell| self.data.iter().filter(|c| **c == cell).count();
+        match count(Cell::Start) {
+            1 => (),
+            n => return Err(MapError::StartCount(n)),
+        }
+        match count(Cell::Finish) {
+            n if n > 0 && n == self.finishes.len() => (),
+            n => return Err(MapError::FinishCount(n)),
+        }
+        let contains = |pos: MapPos| pos.x < self.rows() && pos.y < self.cols();
+        if !contains(self.start) || self[self.start.x][self.start.y] != Cell::Start {
+            return Err(MapError::StartPosition(self.start));
+        }
+        for (i, finish) in self.finishes.iter().enumerate() {
+            if !contains(*finish)
+                || self[finish.x][finish.y] != Cell::Finish
+                || self.finishes[..i].contains(finish)
+            {
+                return Err(MapError::FinishPosition(*finish));
+            }
+        }
+        match count(Cell::Waypoint) {
+            n if n == self.waypoints.len() => (),
+            n => return Err(MapError::WaypointCount(n)),
+        }
+        for (i, waypoint) in self.waypoints.iter().enumerate() {
+            if !contains(*waypoint)
+                || self[waypoint.x][waypoint.y] != Cell::Waypoint
+                || self.waypoints[..i].contains(waypoint)
+            {
+                return Err(MapError::WaypointPosition(*waypoint));
+            }
+        }
+        // начальные и целевые клетки агентов проходимы и не повторяются
+        for (i, agent) in self.agents.iter().enumerate() {
+            for pos in &[agent.start, agent.goal] {
+                if !contains(*pos) || self[pos.x][pos.y] == Cell::Impassable {
+                    return Err(MapError::Agent(*pos));
+                }
+            }
+            let other = &self.agents[..i];
+            if other.iter().any(|a| a.start == agent.start) {
+                return Err(MapError::Agent(agent.start));
+            }
+            if other.iter().any(|a| a.goal == agent.goal) {
+                return Err(MapError::Agent(agent.goal));
+            }
+        }
+        Ok(())
     }
 
     fn get(&self, x: usize, y: usize) -> Cell {
@@ -101,12 +715,307 @@ impl Map {
         self.cols - 2
     }
 
-    // изменеие состояния клетки
+    // клетка находится в пределах карты
+    pub fn contains(&self, pos: MapPos) -> bool {
+        pos.x < self.rows() && pos.y < self.cols()
+    }
+
+    // состояние клетки с проверкой границ карты
+    pub fn try_get(&self, pos: MapPos) -> Result<Cell, MapError> {
+        if self.contains(pos) {
+            Ok(self[pos.x][pos.y])
+        } else {
+            Err(MapError::OutOfBounds(pos))
+        }
+    }
+
+    // изменение состояния клетки с проверкой границ карты, см. set_cell
+    pub fn try_set(&mut self, cell: Cell, pos: MapPos) -> Result<(), MapError> {
+        if self.contains(pos) {
+            self.set_cell(cell, pos);
+            Ok(())
+        } else {
+            Err(MapError::OutOfBounds(pos))
+        }
+    }
+
+    pub fn start(&self) -> MapPos {
+        self.start
+    }
+
+    // конечные клетки в порядке добавления
+    pub fn finishes(&self) -> &[MapPos] {
+        &self.finishes
+    }
+
+    // расстояние до ближайшей конечной клетки
+    pub fn finish_distance(&self, pos: MapPos) -> f64 {
+        self.finishes
+            .iter()
+            .map(|f| self.estimate(pos, *f))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    // смена формы клеток, клетки и их координаты сохраняются,
+    // направления, которых нет у новой формы, удаляются
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        for passage in self.passages.iter_mut() {
+            if passage.is_some_and(|p| !p.direction.fits(topology)) {
+                *passage = None;
+            }
+        }
+    }
+
+    pub fn wrap(&self) -> bool {
+        self.wrap
+    }
+
+    // соединение краёв карты с противоположными, стена по периметру
+    // остаётся, но соседи клеток у края находятся на другой стороне
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    // соединены ли верхний край с нижним и левый с правым; строки шестиугольников
+    // чередуют сдвиг, поэтому при нечётном числе строк замыкаются только столбцы
+    pub fn wraps(&self) -> (bool, bool) {
+        let rows = self.wrap && (self.topology == Topology::Square || self.rows() & 1 == 0);
+        (rows, self.wrap)
+    }
+
+    // соседняя клетка со смещением (dx, dy), None за краем незамкнутой карты
+    fn offset(&self, pos: MapPos, dx: isize, dy: isize) -> Option<MapPos> {
+        let (wrap_rows, wrap_cols) = self.wraps();
+        let shift = |v: usize, d: isize, len: usize, wrap: bool| {
+            let v = v as isize + d;
+            if wrap {
+                Some(v.rem_euclid(len as isize) as usize)
+            } else if v >= 0 && v < len as isize {
+                Some(v as usize)
+            } else {
+                None
+            }
+        };
+        Some(MapPos::new(
+            shift(pos.x, dx, self.rows(), wrap_rows)?,
+            shift(pos.y, dy, self.cols(), wrap_cols)?,
+        ))
+    }
+
+    // промежуточные клетки в порядке посещения
+    pub fn waypoints(&self) -> &[MapPos] {
+        &self.waypoints
+    }
+
+    // изменение порядка посещения промежуточных клеток,
+    // новый порядок должен содержать те же клетки
+    pub fn set_waypoints(&mut self, waypoints: Vec<MapPos>) -> bool {
+        let same = waypoints.len() == self.waypoints.len()
+            && waypoints.iter().all(|w| self.waypoints.contains(w))
+            && self.waypoints.iter().all(|w| waypoints.contains(w));
+        if same {
+            self.waypoints = waypoints;
+        }
+        same
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    // клетка является начальной или целевой для одного из агентов
+    pub fn is_agent_cell(&self, pos: MapPos) -> bool {
+        self.agents.iter().any(|a| a.start == pos || a.goal == pos)
+    }
+
+    // добавление агента, его клетки должны быть проходимы, а начальная
+    // и целевая клетки не совпадать с начальной и целевой клетками других агентов
+    pub fn add_agent(&mut self, agent: Agent) -> bool {
+        let passable = |pos: MapPos| {
+            pos.x < self.rows() && pos.y < self.cols() && self[pos.x][pos.y] != Cell::Impassable
+        };
+        let taken = self
+            .agents
+            .iter()
+            .any(|a| a.start == agent.start || a.goal == agent.goal);
+        if agent.start == agent.goal || taken || !passable(agent.start) || !passable(agent.goal) {
+            return false;
+        }
+        self.agents.push(agent);
+        true
+    }
+
+    // удаление агента, у которого начальная или целевая клетка совпадает с указанной
+    pub fn remove_agent(&mut self, pos: MapPos) -> bool {
+        match self
+            .agents
+            .iter()
+            .position(|a| a.start == pos || a.goal == pos)
+        {
+            Some(i) => {
+                self.agents.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // список конечных или промежуточных клеток
+    fn markers_mut(&mut self, cell: Cell) -> &mut Vec<MapPos> {
+        match cell {
+            Cell::Finish => &mut self.finishes,
+            _ => &mut self.waypoints,
+        }
+    }
+
+    // перемещение конечной или промежуточной клетки на свободную клетку
+    fn move_marker(&mut self, cell: Cell, from: MapPos, to: MapPos) -> bool {
+        if self[to.x][to.y] != Cell::Passable {
+            return false;
+        }
+        let markers = self.markers_mut(cell);
+        match markers.iter().position(|m| *m == from) {
+            Some(i) => markers[i] = to,
+            None => return false,
+        }
+        self[from.x][from.y] = Cell::Passable;
+        self[to.x][to.y] = cell;
+        true
+    }
+
+    // удаление конечной или промежуточной клетки
+    fn remove_marker(&mut self, cell: Cell, pos: MapPos) -> bool {
+        let markers = self.markers_mut(cell);
+        match markers.iter().position(|m| *m == pos) {
+            Some(i) => markers.remove(i),
+            None => return false,
+        };
+        self[pos.x][pos.y] = Cell::Passable;
+        true
+    }
+
+    // перемещение конечной клетки на свободную клетку
+    pub fn move_finish(&mut self, from: MapPos, to: MapPos) -> bool {
+        self.move_marker(Cell::Finish, from, to)
+    }
+
+    // удаление конечной клетки, последняя конечная клетка не удаляется
+    pub fn remove_finish(&mut self, pos: MapPos) -> bool {
+        self.finishes.len() > 1 && self.remove_marker(Cell::Finish, pos)
+    }
+
+    // перемещение промежуточной клетки с сохранением её места в порядке посещения
+    pub fn move_waypoint(&mut self, from: MapPos, to: MapPos) -> bool {
+        self.move_marker(Cell::Waypoint, from, to)
+    }
+
+    pub fn remove_waypoint(&mut self, pos: MapPos) -> bool {
+        self.remove_marker(Cell::Waypoint, pos)
+    }
+
+    // вес клетки, клетка за пределами карты вызывает панику, как и индексация,
+    // см. try_weight
+    pub fn weight(&self, pos: MapPos) -> u8 {
+        assert!(self.contains(pos), "{}", MapError::OutOfBounds(pos));
+        self.weights[self.padded(pos)]
+    }
+
+    // вес клетки с проверкой границ карты
+    pub fn try_weight(&self, pos: MapPos) -> Result<u8, MapError> {
+        if self.contains(pos) {
+            Ok(self.weight(pos))
+        } else {
+            Err(MapError::OutOfBounds(pos))
+        }
+    }
+
+    // изменение веса клетки, вес ограничивается диапазоном 1..=MAX_WEIGHT,
+    // клетка за пределами карты вызывает панику, см. try_set_weight
+    pub fn set_weight(&mut self, pos: MapPos, weight: u8) {
+        assert!(self.contains(pos), "{}", MapError::OutOfBounds(pos));
+        let i = self.padded(pos);
+        self.weights[i] = weight.clamp(1, MAX_WEIGHT);
+    }
+
+    // изменение веса клетки с проверкой границ карты
+    pub fn try_set_weight(&mut self, pos: MapPos, weight: u8) -> Result<(), MapError> {
+        if self.contains(pos) {
+            self.set_weight(pos, weight);
+            Ok(())
+        } else {
+            Err(MapError::OutOfBounds(pos))
+        }
+    }
+
+    // индекс клетки в массивах со стеной по периметру
+    fn padded(&self, pos: MapPos) -> usize {
+        (pos.x + 1) * self.cols + pos.y + 1
+    }
+
+    // направление клетки с проверкой границ карты
+    pub fn passage(&self, pos: MapPos) -> Result<Option<Passage>, MapError> {
+        if self.contains(pos) {
+            Ok(self.passage_at(pos))
+        } else {
+            Err(MapError::OutOfBounds(pos))
+        }
+    }
+
+    // направление клетки в пределах карты, используется при поиске соседей
+    fn passage_at(&self, pos: MapPos) -> Option<Passage> {
+        self.passages[self.padded(pos)]
+    }
+
+    // изменение направления клетки с проверкой границ карты, у стен направлений
+    // нет, а у шестиугольных клеток нет направлений на север и юг
+    pub fn set_passage(&mut self, pos: MapPos, passage: Option<Passage>) -> Result<(), MapError> {
+        if !self.contains(pos) {
+            return Err(MapError::OutOfBounds(pos));
+        }
+        if let Some(p) = passage {
+            if self[pos.x][pos.y] == Cell::Impassable || !p.direction.fits(self.topology) {
+                return Err(MapError::Passage(pos));
+            }
+        }
+        let i = self.padded(pos);
+        self.passages[i] = passage;
+        Ok(())
+    }
+
+    // направленные клетки в порядке обхода по строкам
+    pub fn passages(&self) -> Vec<(MapPos, Passage)> {
+        let mut passages = Vec::new();
+        for i in 0..self.rows() {
+            for j in 0..self.cols() {
+                let pos = MapPos::new(i, j);
+                if let Some(passage) = self.passage_at(pos) {
+                    passages.push((pos, passage));
+                }
+            }
+        }
+        passages
+    }
+
+    // изменеие состояния клетки, конечная и промежуточная клетки
+    // добавляются к уже существующим
     pub fn set_cell(&mut self, cell: Cell, pos: MapPos) {
         if self[pos.x][pos.y] == Cell::Passable {
             match cell {
                 Cell::Passable | Cell::Visited => (),
-                Cell::Impassable | Cell::InQueue => self[pos.x][pos.y] = cell,
+                // клетки агентов остаются проходимыми
+                Cell::Impassable if self.is_agent_cell(pos) => (),
+                Cell::Impassable => {
+                    self[pos.x][pos.y] = cell;
+                    // у стен нет направлений
+                    let i = self.padded(pos);
+                    self.passages[i] = None;
+                }
+                Cell::InQueue => self[pos.x][pos.y] = cell,
                 Cell::Start => {
                     let start = self.start;
                     self[start.x][start.y] = Cell::Passable;
@@ -114,10 +1023,12 @@ impl Map {
                     self.start = pos;
                 }
                 Cell::Finish => {
-                    let finish = self.finish;
-                    self[finish.x][finish.y] = Cell::Passable;
                     self[pos.x][pos.y] = Cell::Finish;
-                    self.finish = pos;
+                    self.finishes.push(pos);
+                }
+                Cell::Waypoint => {
+                    self[pos.x][pos.y] = Cell::Waypoint;
+                    self.waypoints.push(pos);
                 }
             }
         } else {
@@ -137,15 +1048,20 @@ impl Map {
         }
     }
 
-    // удаление всех стен
+    // удаление всех стен, весов и направлений клеток
     pub fn clear(&mut self) {
         for i in 0..self.rows() {
             for j in 0..self.cols() {
-                if !(self[i][j] == Cell::Start || self[i][j] == Cell::Finish) {
+                if self[i][j] != Cell::Start
+                    && self[i][j] != Cell::Finish
+                    && self[i][j] != Cell::Waypoint
+                {
                     self[i][j] = Cell::Passable;
                 }
             }
         }
+        self.weights.iter_mut().for_each(|w| *w = 1);
+        self.passages.iter_mut().for_each(|p| *p = None);
     }
 
     // удаление пути
@@ -157,12 +1073,180 @@ impl Map {
     }
 
     // рассчёт евклидова расстояния
-    fn distance(p: MapPos, q: MapPos) -> f64 {
+    pub fn distance(p: MapPos, q: MapPos) -> f64 {
         ((p.x as f64 - q.x as f64).powi(2) + (p.y as f64 - q.y as f64).powi(2)).sqrt()
     }
 
+    // расстояние между клетками без учёта стен и весов: евклидово
+    // для квадратных клеток и число шагов для шестиугольных,
+    // на замкнутой карте - кратчайшее с переходом через края
+    pub fn estimate(&self, p: MapPos, q: MapPos) -> f64 {
+        let (wrap_rows, wrap_cols) = self.wraps();
+        if !wrap_rows && !wrap_cols {
+            return match self.topology {
+                Topology::Square => Map::distance(p, q),
+                Topology::Hex => hex::distance(p, q) as f64,
+            };
+        }
+        let shifts = |wrap: bool, len: usize| {
+            let len = len as isize;
+            if wrap {
+                vec![-len, 0, len]
+            } else {
+                vec![0]
+            }
+        };
+        let (x, y) = (q.x as isize, q.y as isize);
+        let mut best = f64::INFINITY;
+        // копии клетки q на соседних листах развёртки тора
+        for sx in shifts(wrap_rows, self.rows()) {
+            for sy in shifts(wrap_cols, self.cols()) {
+                let distance = match self.topology {
+                    Topology::Square => {
+                        let dx = (p.x as isize - x - sx) as f64;
+                        let dy = (p.y as isize - y - sy) as f64;
+                        dx.hypot(dy)
+                    }
+                    Topology::Hex => hex::Axial::from_coords(p.x as isize, p.y as isize)
+                        .distance(hex::Axial::from_coords(x + sx, y + sy))
+                        as f64,
+                };
+                best = best.min(distance);
+            }
+        }
+        best
+    }
+
+    // стоимость перехода между соседними клетками с учётом их весов и подъёма
+    // по склонам, не меньше оценки расстояния, поэтому эвристика А* остаётся
+    // допустимой; обратный переход может стоить иначе
+    pub fn cost(&self, p: MapPos, q: MapPos) -> f64 {
+        let weight = f64::from(self.weight(p)) + f64::from(self.weight(q));
+        let uphill = [p, q].iter().any(|pos| match self.passage_at(*pos) {
+            Some(Passage {
+                kind: PassageKind::Slope,
+                direction,
+            }) => self.along(p, q, direction) < 0f64,
+            _ => false,
+        });
+        let factor = if uphill { UPHILL_FACTOR } else { 1f64 };
+        self.estimate(p, q) * weight / 2f64 * factor
+    }
+
+    // шаг между соседними клетками на плоскости (по строкам, по столбцам),
+    // переход через край замкнутой карты - шаг в сторону края
+    fn step(&self, p: MapPos, q: MapPos) -> (f64, f64) {
+        let (a, b, height) = match self.topology {
+            Topology::Square => ((p.x as f64, p.y as f64), (q.x as f64, q.y as f64), 1f64),
+            Topology::Hex => (hex::plane(p), hex::plane(q), hex::ROW_HEIGHT),
+        };
+        // шаг к соседу не длиннее 1.5 ни по одной оси
+        let unwrap = |d: f64, period: f64| match d {
+            d if d > 1.5 => d - period,
+            d if d < -1.5 => d + period,
+            d => d,
+        };
+        (
+            unwrap(b.0 - a.0, self.rows() as f64 * height),
+            unwrap(b.1 - a.1, self.cols() as f64),
+        )
+    }
+
+    // проекция шага из p в q на направление, положительна для шагов по направлению
+    fn along(&self, p: MapPos, q: MapPos, direction: Direction) -> f64 {
+        let (dx, dy) = self.step(p, q);
+        let (vx, vy) = direction.vector();
+        dx * vx + dy * vy
+    }
+
+    // разрешён ли переход между соседними клетками: из конвейера можно выйти
+    // только по его направлению, на пандус - войти только по его направлению
+    pub fn allows(&self, from: MapPos, to: MapPos) -> bool {
+        let forward = |pos: MapPos, kind: PassageKind| match self.passage_at(pos) {
+            Some(p) if p.kind == kind => self.along(from, to, p.direction) > 1e-9,
+            _ => true,
+        };
+        forward(from, PassageKind::Conveyor) && forward(to, PassageKind::Ramp)
+    }
+
     // поиск сосдедей доступных для перехода
-    fn neighbors(&self, pos: MapPos) -> IntoIter<MapPos> {
+    pub fn neighbors(&self, pos: MapPos) -> IntoIter<MapPos> {
+        let mut vec = self.adjacent(pos);
+        vec.retain(|n| self.allows(pos, *n));
+        vec.into_iter()
+    }
+
+    // соседи, из которых можно перейти в клетку, для поиска от целей
+    pub fn predecessors(&self, pos: MapPos) -> IntoIter<MapPos> {
+        let mut vec = self.adjacent(pos);
+        vec.retain(|n| self.allows(*n, pos));
+        vec.into_iter()
+    }
+
+    // проходимые соседние клетки без учёта направлений
+    fn adjacent(&self, pos: MapPos) -> Vec<MapPos> {
+        match self.topology {
+            Topology::Square if self.wrap => self.wrapped_square_neighbors(pos),
+            Topology::Square => self.square_neighbors(pos),
+            Topology::Hex => self.hex_neighbors(pos),
+        }
+    }
+
+    // шесть соседей шестиугольной клетки, смещения зависят от чётности строки
+    fn hex_neighbors(&self, pos: MapPos) -> Vec<MapPos> {
+        const EVEN: [(isize, isize); 6] = [(0, 1), (-1, 0), (-1, -1), (0, -1), (1, -1), (1, 0)];
+        const ODD: [(isize, isize); 6] = [(0, 1), (-1, 1), (-1, 0), (0, -1), (1, 0), (1, 1)];
+        let deltas = if pos.x & 1 == 0 { &EVEN } else { &ODD };
+        let mut vec = Vec::with_capacity(6);
+        for (dx, dy) in deltas.iter() {
+            if let Some(n) = self.offset(pos, *dx, *dy) {
+                if self[n.x][n.y] != Cell::Impassable {
+                    Map::push_neighbor(&mut vec, pos, n);
+                }
+            }
+        }
+        vec
+    }
+
+    // на узкой замкнутой карте разные смещения могут вести в одну клетку
+    // или в саму клетку, такие соседи не добавляются
+    fn push_neighbor(vec: &mut Vec<MapPos>, pos: MapPos, neighbor: MapPos) {
+        if neighbor != pos && !vec.contains(&neighbor) {
+            vec.push(neighbor);
+        }
+    }
+
+    // восемь соседей квадратной клетки замкнутой карты, правило
+    // диагональных переходов то же, что и у незамкнутой
+    fn wrapped_square_neighbors(&self, pos: MapPos) -> Vec<MapPos> {
+        const SIDES: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+        let free = |dx: isize, dy: isize| {
+            self.offset(pos, dx, dy)
+                .filter(|n| self[n.x][n.y] != Cell::Impassable)
+        };
+        let mut vec = Vec::with_capacity(8);
+        let mut sides = [false; 4];
+        for (i, &(dx, dy)) in SIDES.iter().enumerate() {
+            if let Some(n) = free(dx, dy) {
+                sides[i] = true;
+                Map::push_neighbor(&mut vec, pos, n);
+            }
+        }
+        for i in 0..4 {
+            let j = (i + 1) % 4;
+            if !sides[i] && !sides[j] {
+                continue;
+            }
+            if let Some(n) = free(SIDES[i].0 + SIDES[j].0, SIDES[i].1 + SIDES[j].1) {
+                Map::push_neighbor(&mut vec, pos, n);
+            }
+        }
+        vec
+    }
+
+    // восемь соседей квадратной клетки, диагональный переход возможен,
+    // если проходима хотя бы одна из прилегающих к нему клеток
+    fn square_neighbors(&self, pos: MapPos) -> Vec<MapPos> {
         let mut vec = Vec::with_capacity(8);
         let mut s = [false; 4];
         let mut d = [false; 4];
@@ -201,7 +1285,7 @@ impl Map {
         if d[3] && self.get(pos.x + 1, pos.y - 1) != Cell::Impassable {
             vec.push(MapPos::new(pos.x, pos.y - 2));
         }
-        vec.into_iter()
+        vec
     }
 
     // загрузка карты
@@ -209,8 +1293,19 @@ impl Map {
         self.cols = map.cols;
         self.data.clear();
         self.data.extend_from_slice(&map.data);
+        self.weights.clear();
+        self.weights.extend_from_slice(&map.weights);
+        self.passages.clear();
+        self.passages.extend_from_slice(&map.passages);
         self.start = map.start;
-        self.finish = map.finish;
+        self.finishes.clear();
+        self.finishes.extend_from_slice(&map.finishes);
+        self.waypoints.clear();
+        self.waypoints.extend_from_slice(&map.waypoints);
+        self.agents.clear();
+        self.agents.extend_from_slice(&map.agents);
+        self.topology = map.topology;
+        self.wrap = map.wrap;
     }
 }
 
@@ -220,21 +1315,25 @@ pub enum SearchStatus {
     Found(f64),
     NotFound,
     Searching,
+    // бюджет поиска исчерпан, частичный путь до клетки, ближайшей к цели
+    // по эвристике, записан от конца к началу
+    BudgetExhausted { path: Vec<MapPos>, cost: f64 },
 }
 
-// алгоритм поиска пути
-#[derive(Debug, PartialEq)]
-pub enum Algorithm {
-    BreadthFirstSearch,
-    Dijkstra,
-    AStar,
+// условие завершения поиска при нескольких конечных клетках
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GoalMode {
+    // поиск завершается на ближайшей конечной клетке
+    Nearest,
+    // поиск продолжается до достижения всех конечных клеток
+    All,
 }
 
 // родительская клетка и стоимость пути
 #[derive(Debug, Clone, Copy, PartialEq)]
-struct PosState {
-    pos: MapPos,
-    cost: f64,
+pub struct PosState {
+    pub pos: MapPos,
+    pub cost: f64,
 }
 
 impl Eq for PosState {}
@@ -255,97 +1354,752 @@ impl PartialOrd for PosState {
     }
 }
 
+// подписчик на события поиска
+pub type Observer = Box<dyn FnMut(&SearchEvent) + Send>;
+
 // контекст поиска пути
 pub struct ShortestPath {
     pub map: Map,
-    queue: BinaryHeap<PosState>,
-    visited: HashMap<MapPos, PosState>,
-    algorithm: Algorithm,
+    algorithm: Box<dyn SearchAlgorithm>,
+    goal_mode: GoalMode,
+    // события последней итерации поиска
+    events: Vec<SearchEvent>,
+    observers: Vec<Observer>,
+    budget: SearchBudget,
+    // время инициализации поиска
+    started: Instant,
 }
+
 impl ShortestPath {
-    pub fn new(map: Map, algorithm: Algorithm) -> Self {
-        let queue = BinaryHeap::new();
-        let visited = HashMap::new();
+    pub fn new(map: Map, algorithm: Box<dyn SearchAlgorithm>) -> Self {
         Self {
             map,
-            queue,
-            visited,
             algorithm,
+            goal_mode: GoalMode::Nearest,
+            events: Vec::new(),
+            observers: Vec::new(),
+            budget: SearchBudget::default(),
+            started: Instant::now(),
+        }
+    }
+
+    // подписка на события поиска, подписчик вызывается после каждой итерации
+    pub fn subscribe<F>(&mut self, observer: F)
+    where
+        F: FnMut(&SearchEvent) + Send + 'static,
+    {
+        self.observers.push(Box::new(observer));
+    }
+
+    // события последней итерации поиска
+    pub fn last_events(&self) -> &[SearchEvent] {
+        &self.events
+    }
+
+    // итератор по событиям поиска, итерации выполняются по мере чтения событий,
+    // поиск должен быть инициализирован
+    pub fn events(&mut self) -> Events<'_> {
+        Events {
+            search: self,
+            pending: VecDeque::new(),
+            done: false,
         }
     }
 
+    // замена алгоритма без копирования карты, поиск нужно инициализировать заново
+    pub fn set_algorithm(&mut self, algorithm: Box<dyn SearchAlgorithm>) {
+        self.algorithm = algorithm;
+    }
+
+    pub fn set_goal_mode(&mut self, goal_mode: GoalMode) {
+        self.goal_mode = goal_mode;
+    }
+
+    // ограничения поиска, время отсчитывается от инициализации поиска
+    pub fn set_budget(&mut self, budget: SearchBudget) {
+        self.budget = budget;
+    }
+
     // следующая итерация поиска
     pub fn next(&mut self) -> SearchStatus {
-        if let Some(current) = self.queue.pop() {
-            if current.pos == self.map.finish {
-                return SearchStatus::Found(self.visited[&current.pos].cost);
+        self.events.clear();
+        if self.budget.exhausted(self.stats().expanded, self.started) {
+            return self.partial();
+        }
+        let status = self.algorithm.step(&mut self.map, &mut self.events);
+        for observer in &mut self.observers {
+            for event in &self.events {
+                observer(event);
             }
-            for pos in self.map.neighbors(current.pos) {
-                let new_cost = self.visited[&current.pos].cost + Map::distance(current.pos, pos);
+        }
+        status
+    }
 
-                if let Some(info) = self.visited.get(&pos) {
-                    if self.algorithm == Algorithm::BreadthFirstSearch || new_cost >= info.cost {
-                        continue;
+    // поиск пути без остановок
+    pub fn run(&mut self) -> SearchStatus {
+        self.init();
+        loop {
+            match self.next() {
+                SearchStatus::Searching => (),
+                status => return status,
+            }
+        }
+    }
+
+    // построение пути до ближайшей достигнутой конечной клетки
+    pub fn path(&self) -> Option<Vec<MapPos>> {
+        self.algorithm
+            .reached()
+            .first()
+            .and_then(|goal| self.path_to(*goal))
+    }
+
+    // пути до всех достигнутых конечных клеток со стоимостью, от ближайшей к дальней
+    pub fn paths(&self) -> Vec<(MapPos, f64, Vec<MapPos>)> {
+        self.algorithm
+            .reached()
+            .iter()
+            .filter_map(|goal| {
+                let cost = self.algorithm.cost(*goal)?;
+                self.path_to(*goal).map(|path| (*goal, cost, path))
+            })
+            .collect()
+    }
+
+    // построение пути до клетки, путь записывается от конца к началу
+    pub fn path_to(&self, goal: MapPos) -> Option<Vec<MapPos>> {
+        self.algorithm.path(goal)
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.algorithm.stats()
+    }
+
+    // лучший частичный путь при исчерпании бюджета
+    fn partial(&self) -> SearchStatus {
+        let closest = self.algorithm.closest(&self.map);
+        SearchStatus::BudgetExhausted {
+            path: closest
+                .and_then(|pos| self.path_to(pos))
+                .unwrap_or_default(),
+            cost: closest
+                .and_then(|pos| self.algorithm.cost(pos))
+                .unwrap_or(0f64),
+        }
+    }
+
+    // инициализация поиска в соответствии состояния карты
+    pub fn init(&mut self) {
+        self.events.clear();
+        self.started = Instant::now();
+        self.algorithm.init(&self.map, self.goal_mode);
+    }
+}
+
+// события поиска, полученные ShortestPath::events
+pub struct Events<'a> {
+    search: &'a mut ShortestPath,
+    pending: VecDeque<SearchEvent>,
+    done: bool,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = SearchEvent;
+
+    fn next(&mut self) -> Option<SearchEvent> {
+        while self.pending.is_empty() && !self.done {
+            self.done = self.search.next() != SearchStatus::Searching;
+            self.pending.extend(self.search.last_events());
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use quickcheck::{Arbitrary, Gen};
+    use search::{BestFirst, CancelToken};
+    use std::sync::{Arc, Mutex};
+
+    fn parse(text: &str) -> Map {
+        format::from_text(text).unwrap()
+    }
+
+    fn algorithms() -> Vec<BestFirst> {
+        vec![
+            BestFirst::breadth_first(),
+            BestFirst::dijkstra(),
+            BestFirst::astar(),
+        ]
+    }
+
+    fn search(map: &Map, algorithm: BestFirst) -> ShortestPath {
+        let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+        search.run();
+        search
+    }
+
+    fn sorted(iter: IntoIter<MapPos>) -> Vec<(usize, usize)> {
+        let mut vec = iter.map(|p| (p.x, p.y)).collect::<Vec<_>>();
+        vec.sort();
+        vec
+    }
+
+    // путь записан от конечной клетки к начальной, соседние клетки пути
+    // связаны переходом и не являются стенами
+    fn check_path(map: &Map, path: &[MapPos]) {
+        assert_eq!(path[0], map.finishes()[0]);
+        assert_eq!(path[path.len() - 1], map.start());
+        for w in path.windows(2) {
+            assert!(map.neighbors(w[1]).any(|p| p == w[0]));
+            assert_ne!(map[w[0].x][w[0].y], Cell::Impassable);
+        }
+    }
+
+    #[test]
+    fn new_rejects_invalid_sizes() {
+        assert_eq!(
+            Map::new(0, 5).unwrap_err(),
+            MapError::InvalidSize { rows: 0, cols: 5 }
+        );
+        assert!(Map::new(5, 0).is_err());
+        assert!(Map::new(1, 1).is_err());
+        assert!(Map::new(usize::MAX, 2).is_err());
+        assert!(Map::new(1 << 20, 1 << 20).is_err());
+        assert!(Map::new(4094, 4094).is_ok());
+        assert!(Map::from_cells(usize::MAX, 2, &[]).is_err());
+        assert!(Map::from_cells(1 << 40, 1 << 40, &[Cell::Start, Cell::Finish]).is_err());
+        assert_eq!(
+            Map::from_cells(0, 2, &[]).unwrap_err(),
+            MapError::InvalidSize { rows: 0, cols: 2 }
+        );
+        let map = Map::new(1, 2).unwrap();
+        assert_eq!((map.rows(), map.cols()), (1, 2));
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn try_get_and_try_set_check_bounds() {
+        let mut map = Map::new(2, 3).unwrap();
+        assert_eq!(map.try_get(MapPos::new(0, 0)), Ok(Cell::Start));
+        assert_eq!(
+            map.try_get(MapPos::new(2, 0)),
+            Err(MapError::OutOfBounds(MapPos::new(2, 0)))
+        );
+        assert!(map.try_set(Cell::Impassable, MapPos::new(0, 1)).is_ok());
+        assert_eq!(map.try_get(MapPos::new(0, 1)), Ok(Cell::Impassable));
+        assert!(map.try_set(Cell::Impassable, MapPos::new(0, 3)).is_err());
+        assert_eq!(map.try_set_weight(MapPos::new(1, 2), 5), Ok(()));
+        assert_eq!(map.try_weight(MapPos::new(1, 2)), Ok(5));
+        assert_eq!(
+            map.try_set_weight(MapPos::new(0, 3), 5),
+            Err(MapError::OutOfBounds(MapPos::new(0, 3)))
+        );
+        assert!(map.try_weight(MapPos::new(2, 0)).is_err());
+    }
+
+    #[test]
+    fn neighbors_in_open_map() {
+        let map = parse("...\n.S.\n..F\n");
+        assert_eq!(map.neighbors(MapPos::new(1, 1)).count(), 8);
+        assert_eq!(
+            sorted(map.neighbors(MapPos::new(0, 0))),
+            vec![(0, 1), (1, 0), (1, 1)]
+        );
+        assert_eq!(map.neighbors(MapPos::new(2, 2)).count(), 3);
+    }
+
+    #[test]
+    fn neighbors_skip_walls() {
+        let map = parse("S#.\n...\n.#F\n");
+        assert_eq!(
+            sorted(map.neighbors(MapPos::new(1, 1))),
+            vec![(0, 0), (0, 2), (1, 0), (1, 2), (2, 0), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn diagonal_needs_one_free_side() {
+        // обе соседние по сторонам клетки - стены, диагональный переход запрещён
+        let map = parse("S#.\n#..\n..F\n");
+        assert!(map.neighbors(MapPos::new(0, 0)).next().is_none());
+        assert!(!map
+            .neighbors(MapPos::new(1, 1))
+            .any(|p| p == MapPos::new(0, 0)));
+        // одна из соседних клеток свободна, переход разрешён
+        let map = parse("S#.\n...\n..F\n");
+        assert!(map
+            .neighbors(MapPos::new(0, 0))
+            .any(|p| p == MapPos::new(1, 1)));
+    }
+
+    #[test]
+    fn cost_uses_weights() {
+        let mut map = parse("S.\n.F\n");
+        assert_eq!(map.cost(MapPos::new(0, 0), MapPos::new(0, 1)), 1f64);
+        map.set_weight(MapPos::new(0, 1), 3);
+        assert_eq!(map.cost(MapPos::new(0, 0), MapPos::new(0, 1)), 2f64);
+        let diagonal = map.cost(MapPos::new(0, 0), MapPos::new(1, 1));
+        assert!((diagonal - 2f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hex_neighbors_and_cost() {
+        let mut map = parse("S...\n....\n....\n...F\n");
+        map.set_topology(Topology::Hex);
+        // в чётной строке соседи сверху и снизу сдвинуты влево, в нечётной - вправо
+        assert_eq!(
+            sorted(map.neighbors(MapPos::new(2, 1))),
+            vec![(1, 0), (1, 1), (2, 0), (2, 2), (3, 0), (3, 1)]
+        );
+        assert_eq!(
+            sorted(map.neighbors(MapPos::new(1, 1))),
+            vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]
+        );
+        assert_eq!(
+            sorted(map.neighbors(MapPos::new(0, 0))),
+            vec![(0, 1), (1, 0)]
+        );
+        // стоимость пути равна числу шагов между шестиугольниками
+        for algorithm in algorithms() {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            assert!(search.run() == SearchStatus::Found(5f64));
+            check_path(&search.map, &search.path().unwrap());
+        }
+    }
+
+    #[test]
+    fn wrapped_edges_connect() {
+        let mut map = parse("S.#..F\n..#...\n..#...\n");
+        let mut search = ShortestPath::new(map.clone(), Box::new(BestFirst::astar()));
+        assert!(search.run() == SearchStatus::NotFound);
+        map.set_wrap(true);
+        assert_eq!(map.neighbors(MapPos::new(0, 0)).count(), 8);
+        assert_eq!(map.estimate(MapPos::new(0, 0), MapPos::new(0, 5)), 1f64);
+        for algorithm in algorithms() {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            assert!(search.run() == SearchStatus::Found(1f64));
+            check_path(&search.map, &search.path().unwrap());
+        }
+        // соседи взаимны и находятся на расстоянии одного шага,
+        // при нечётном числе строк шестиугольники замыкаются только по столбцам
+        for &(text, topology) in &[
+            ("S...\n....\n...F\n", Topology::Square),
+            ("S...\n....\n....\n...F\n", Topology::Hex),
+            ("S...\n....\n...F\n", Topology::Hex),
+        ] {
+            let mut map = parse(text);
+            map.set_topology(topology);
+            map.set_wrap(true);
+            for x in 0..map.rows() {
+                for y in 0..map.cols() {
+                    let pos = MapPos::new(x, y);
+                    for n in map.neighbors(pos) {
+                        assert!(map.neighbors(n).any(|p| p == pos));
+                        assert!(map.estimate(pos, n) <= map.cost(pos, n) + 1e-9);
                     }
                 }
+            }
+        }
+    }
 
-                let cost = match self.algorithm {
-                    Algorithm::BreadthFirstSearch => current.cost + (self.queue.len() + 1) as f64,
-                    Algorithm::Dijkstra => new_cost,
-                    Algorithm::AStar => new_cost + Map::distance(pos, self.map.finish),
-                };
+    #[test]
+    fn one_way_cells_and_slopes() {
+        let (s, c, f) = (MapPos::new(0, 0), MapPos::new(0, 1), MapPos::new(0, 2));
+        // из конвейера на запад нельзя выйти к конечной клетке, путь идёт в обход
+        let mut map = parse("S.F\n...\n");
+        map.set_passage(c, Some(Passage::new(PassageKind::Conveyor, Direction::W)))
+            .unwrap();
+        assert_eq!(sorted(map.neighbors(c)), vec![(0, 0), (1, 0)]);
+        assert_eq!(sorted(map.predecessors(f)), vec![(1, 1), (1, 2)]);
+        for algorithm in algorithms().into_iter().skip(1) {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            assert!(search.run() == SearchStatus::Found(2f64 * 2f64.sqrt()));
+            check_path(&search.map, &search.path().unwrap());
+        }
+        // на пандус на восток можно войти только с запада
+        map.set_passage(c, Some(Passage::new(PassageKind::Ramp, Direction::E)))
+            .unwrap();
+        assert!(map.allows(s, c) && !map.allows(f, c) && map.allows(c, f));
+        assert_eq!(sorted(map.predecessors(c)), vec![(0, 0), (1, 0)]);
+        // склон спускается на запад: подъём на восток вдвое дороже
+        map.set_passage(c, Some(Passage::new(PassageKind::Slope, Direction::W)))
+            .unwrap();
+        assert_eq!(map.cost(s, c), UPHILL_FACTOR);
+        assert_eq!(map.cost(c, s), 1f64);
+        assert_eq!(map.cost(c, f), UPHILL_FACTOR);
+        assert_eq!(map.cost(MapPos::new(1, 1), c), 1f64);
+        map.clear();
+        assert!(map.passages().is_empty());
+    }
 
-                self.queue.push(PosState { pos, cost });
-                self.visited.insert(
-                    pos,
-                    PosState {
-                        pos: current.pos,
-                        cost: new_cost,
-                    },
-                );
-                self.map.set_cell(Cell::InQueue, pos);
-            }
-            self.map.set_cell(Cell::Visited, current.pos);
-            SearchStatus::Searching
-        } else {
-            SearchStatus::NotFound
+    #[test]
+    fn passages_are_checked() {
+        let mut map = parse("S.#\n..F\n");
+        let east = Some(Passage::new(PassageKind::Conveyor, Direction::E));
+        let north = Some(Passage::new(PassageKind::Ramp, Direction::N));
+        assert_eq!(
+            map.set_passage(MapPos::new(0, 3), east),
+            Err(MapError::OutOfBounds(MapPos::new(0, 3)))
+        );
+        assert_eq!(
+            map.passage(MapPos::new(2, 0)),
+            Err(MapError::OutOfBounds(MapPos::new(2, 0)))
+        );
+        assert_eq!(
+            map.set_passage(MapPos::new(0, 2), east),
+            Err(MapError::Passage(MapPos::new(0, 2)))
+        );
+        // стена и смена формы клеток удаляют направления
+        map.set_passage(MapPos::new(1, 0), east).unwrap();
+        map.set_passage(MapPos::new(1, 1), north).unwrap();
+        map.set_cell(Cell::Impassable, MapPos::new(1, 0));
+        map.set_topology(Topology::Hex);
+        assert!(map.passages().is_empty());
+        assert_eq!(
+            map.set_passage(MapPos::new(1, 1), north),
+            Err(MapError::Passage(MapPos::new(1, 1)))
+        );
+        assert!(map.validate().is_ok());
+        // направление на стене по периметру
+        map.passages[0] = east;
+        assert_eq!(map.validate(), Err(MapError::Passage(MapPos::new(0, 0))));
+    }
+
+    #[test]
+    fn path_walks_parents_from_finish() {
+        let map = parse("S...F\n");
+        let search = search(&map, BestFirst::dijkstra());
+        let path = search.path().unwrap();
+        assert_eq!(
+            path,
+            (0..5).rev().map(|y| MapPos::new(0, y)).collect::<Vec<_>>()
+        );
+        check_path(&search.map, &path);
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let map = parse("S#F\n.#.\n...\n");
+        for algorithm in algorithms() {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            match search.run() {
+                SearchStatus::Found(cost) => {
+                    assert!((cost - (2f64 + 2f64 * 2f64.sqrt())).abs() < 1e-9)
+                }
+                _ => panic!("путь не найден"),
+            }
+            check_path(&search.map, &search.path().unwrap());
         }
     }
 
-    // построение пути
-    pub fn path(&self) -> Option<Vec<MapPos>> {
-        if let Some(info) = self.visited.get(&self.map.finish) {
-            let mut vec = Vec::with_capacity(info.cost as usize);
-            let mut current = self.map.finish;
-            while self.visited[&current].pos != current {
-                vec.push(current);
-                current = self.visited[&current].pos;
-            }
-            vec.push(current);
-            Some(vec)
-        } else {
-            None
+    #[test]
+    fn heavy_cells_are_avoided() {
+        let mut map = parse("S.F\n...\n");
+        map.set_weight(MapPos::new(0, 1), MAX_WEIGHT);
+        let search = search(&map, BestFirst::dijkstra());
+        assert!(!search.path().unwrap().contains(&MapPos::new(0, 1)));
+    }
+
+    #[test]
+    fn not_found_behind_walls() {
+        let map = parse("S#.\n##.\n..F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::astar()));
+        assert!(search.run() == SearchStatus::NotFound);
+        assert!(search.path().is_none());
+    }
+
+    #[test]
+    fn all_goals_are_reached() {
+        let map = parse("F.S.F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::dijkstra()));
+        search.set_goal_mode(GoalMode::All);
+        assert!(search.run() == SearchStatus::Found(2f64));
+        let paths = search.paths();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|(_, cost, _)| *cost == 2f64));
+    }
+
+    #[test]
+    fn nearest_goal_stops_before_farther_ones() {
+        let map = parse("F.S...F\n");
+        for algorithm in algorithms() {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            assert!(search.run() == SearchStatus::Found(2f64));
+            let paths = search.paths();
+            assert_eq!(paths.len(), 1);
+            assert_eq!(paths[0].0, MapPos::new(0, 0));
+            assert_eq!(search.path().unwrap()[0], MapPos::new(0, 0));
         }
     }
 
-    // инициализация поиска в соответствии состояния карты
-    pub fn init(&mut self) {
-        self.queue.clear();
-        self.visited.clear();
-        let cost = match self.algorithm {
-            Algorithm::AStar => Map::distance(self.map.start, self.map.finish),
-            _ => 0f64,
+    #[test]
+    fn all_goals_are_reported_by_distance() {
+        let map = parse("F.S...F\n");
+        for algorithm in algorithms() {
+            let mut search = ShortestPath::new(map.clone(), Box::new(algorithm));
+            search.set_goal_mode(GoalMode::All);
+            // статус поиска - стоимость пути до ближайшей цели
+            assert!(search.run() == SearchStatus::Found(2f64));
+            let paths = search
+                .paths()
+                .into_iter()
+                .map(|(goal, cost, path)| ((goal.x, goal.y), cost, path.len()))
+                .collect::<Vec<_>>();
+            assert_eq!(paths, vec![((0, 0), 2f64, 3), ((0, 6), 4f64, 5)]);
+        }
+    }
+
+    #[test]
+    fn all_goals_skip_unreachable_ones() {
+        let map = parse("F.S.#F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::dijkstra()));
+        search.set_goal_mode(GoalMode::All);
+        assert!(search.run() == SearchStatus::Found(2f64));
+        assert_eq!(search.paths().len(), 1);
+
+        let map = parse("S#F\n.#F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::dijkstra()));
+        search.set_goal_mode(GoalMode::All);
+        assert!(search.run() == SearchStatus::NotFound);
+        assert!(search.paths().is_empty());
+    }
+
+    #[test]
+    fn observers_and_events_see_the_same_order() {
+        let map = parse("S.#\n..F\n");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut observed = ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra()));
+        let sink = seen.clone();
+        observed.subscribe(move |event| sink.lock().unwrap().push(*event));
+        let cost = match observed.run() {
+            SearchStatus::Found(cost) => cost,
+            _ => panic!("путь не найден"),
         };
-        self.queue.push(PosState {
-            pos: self.map.start,
-            cost,
-        });
-        self.visited.insert(
-            self.map.start,
-            PosState {
-                pos: self.map.start,
+
+        let mut search = ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra()));
+        search.init();
+        let events = search.events().collect::<Vec<_>>();
+        assert_eq!(*seen.lock().unwrap(), events);
+
+        // клетка раскрывается после добавления в очередь, её соседи - после
+        // раскрытия, достижение цели - последнее событие
+        let start = map.start();
+        assert_eq!(
+            events[0],
+            SearchEvent::NodeExpanded {
+                pos: start,
                 cost: 0f64,
-            },
+            }
+        );
+        let mut pushed = vec![start];
+        let mut expanded = Vec::new();
+        for event in &events {
+            match *event {
+                SearchEvent::NodeExpanded { pos, .. } => {
+                    assert!(pushed.contains(&pos));
+                    expanded.push(pos);
+                }
+                SearchEvent::NodePushed { pos, parent, .. } => {
+                    assert_eq!(expanded.last(), Some(&parent));
+                    pushed.push(pos);
+                }
+                _ => (),
+            }
+        }
+        let finish = map.finishes()[0];
+        assert_eq!(
+            events[events.len() - 1],
+            SearchEvent::GoalReached { pos: finish, cost }
         );
+        assert_eq!(expanded.last(), Some(&finish));
+
+        // применение событий к исходной карте воспроизводит разметку поиска
+        let mut replayed = map.clone();
+        for event in &events {
+            event.apply(&mut replayed);
+        }
+        assert_eq!(replayed, search.map);
+    }
+
+    #[test]
+    fn events_end_when_frontier_is_exhausted() {
+        let mut search = ShortestPath::new(parse("S#F\n"), Box::new(BestFirst::astar()));
+        search.init();
+        let events = search.events().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], SearchEvent::FrontierExhausted);
+    }
+
+    #[test]
+    fn node_budget_returns_closest_partial_path() {
+        let map = parse("S.......F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::astar()));
+        search.set_budget(SearchBudget {
+            max_nodes: Some(3),
+            ..SearchBudget::default()
+        });
+        match search.run() {
+            SearchStatus::BudgetExhausted { path, cost } => {
+                assert_eq!(path.len(), 4);
+                assert_eq!(path[0], MapPos::new(0, 3));
+                assert_eq!(path[3], MapPos::new(0, 0));
+                assert_eq!(cost, 3f64);
+            }
+            _ => panic!("бюджет не исчерпан"),
+        }
+        assert_eq!(search.stats().expanded, 3);
+    }
+
+    #[test]
+    fn partial_path_heads_for_unreached_goals() {
+        let map = parse("F.S......F\n");
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::astar()));
+        search.set_goal_mode(GoalMode::All);
+        search.set_budget(SearchBudget {
+            max_nodes: Some(6),
+            ..SearchBudget::default()
+        });
+        match search.run() {
+            SearchStatus::BudgetExhausted { path, .. } => {
+                // левая цель уже достигнута, частичный путь ведёт к правой
+                assert_eq!(path[path.len() - 1], MapPos::new(0, 2));
+                assert!(path[0].y > 2);
+            }
+            _ => panic!("бюджет не исчерпан"),
+        }
+    }
+
+    #[test]
+    fn cancelled_search_stops() {
+        let map = parse("S.......F\n");
+        let cancel = CancelToken::new();
+        let mut search = ShortestPath::new(map, Box::new(BestFirst::dijkstra()));
+        search.set_budget(SearchBudget {
+            cancel: Some(cancel.clone()),
+            ..SearchBudget::default()
+        });
+        search.init();
+        assert!(search.next() == SearchStatus::Searching);
+        cancel.cancel();
+        assert!(matches!(
+            search.next(),
+            SearchStatus::BudgetExhausted { .. }
+        ));
+        assert_eq!(search.stats().expanded, 1);
+    }
+
+    // случайная карта для проверки свойств: стены, веса клеток,
+    // одна начальная и одна конечная клетка
+    #[derive(Clone)]
+    struct RandomMap(Map);
+
+    impl fmt::Debug for RandomMap {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "\n{}", format::to_text(&self.0))
+        }
+    }
+
+    impl Arbitrary for RandomMap {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let rows = 1 + usize::arbitrary(g) % 8;
+            let cols = 2 + usize::arbitrary(g) % 7;
+            let mut cells = (0..rows * cols)
+                .map(|_| {
+                    if u8::arbitrary(g) % 10 < 3 {
+                        Cell::Impassable
+                    } else {
+                        Cell::Passable
+                    }
+                })
+                .collect::<Vec<_>>();
+            let start = usize::arbitrary(g) % cells.len();
+            let finish = (start + 1 + usize::arbitrary(g) % (cells.len() - 1)) % cells.len();
+            cells[start] = Cell::Start;
+            cells[finish] = Cell::Finish;
+            let mut map = Map::from_cells(rows, cols, &cells).unwrap();
+            for x in 0..rows {
+                for y in 0..cols {
+                    if bool::arbitrary(g) {
+                        map.set_weight(MapPos::new(x, y), 1 + u8::arbitrary(g) % MAX_WEIGHT);
+                    }
+                }
+            }
+            RandomMap(map)
+        }
+    }
+
+    // стоимость кратчайшего пути алгоритмом Беллмана-Форда
+    fn bellman_ford(map: &Map) -> Option<f64> {
+        let index = |p: MapPos| p.x * map.cols() + p.y;
+        let mut dist = vec![f64::INFINITY; map.rows() * map.cols()];
+        dist[index(map.start())] = 0f64;
+        for _ in 0..dist.len() {
+            for x in 0..map.rows() {
+                for y in 0..map.cols() {
+                    let pos = MapPos::new(x, y);
+                    if map[x][y] == Cell::Impassable || !dist[index(pos)].is_finite() {
+                        continue;
+                    }
+                    for next in map.neighbors(pos) {
+                        let cost = dist[index(pos)] + map.cost(pos, next);
+                        if cost < dist[index(next)] {
+                            dist[index(next)] = cost;
+                        }
+                    }
+                }
+            }
+        }
+        Some(dist[index(map.finishes()[0])]).filter(|d| d.is_finite())
+    }
+
+    fn found(status: SearchStatus) -> Option<f64> {
+        match status {
+            SearchStatus::Found(cost) => Some(cost),
+            _ => None,
+        }
+    }
+
+    quickcheck! {
+        fn dijkstra_and_astar_agree(map: RandomMap) -> bool {
+            let mut dijkstra = ShortestPath::new(map.0.clone(), Box::new(BestFirst::dijkstra()));
+            let mut astar = ShortestPath::new(map.0.clone(), Box::new(BestFirst::astar()));
+            match (found(dijkstra.run()), found(astar.run())) {
+                (Some(a), Some(b)) => (a - b).abs() < 1e-9,
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        fn dijkstra_is_optimal(map: RandomMap) -> bool {
+            let mut search = ShortestPath::new(map.0.clone(), Box::new(BestFirst::dijkstra()));
+            match (found(search.run()), bellman_ford(&map.0)) {
+                (Some(a), Some(b)) => (a - b).abs() < 1e-9,
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        fn paths_are_contiguous(map: RandomMap) -> bool {
+            for algorithm in algorithms() {
+                let search = search(&map.0, algorithm);
+                if let Some(path) = search.path() {
+                    check_path(&search.map, &path);
+                }
+            }
+            true
+        }
+
+        fn not_found_iff_unreachable(map: RandomMap) -> bool {
+            let reachable = bellman_ford(&map.0).is_some();
+            algorithms()
+                .into_iter()
+                .all(|algorithm| {
+                    let mut search = ShortestPath::new(map.0.clone(), Box::new(algorithm));
+                    (search.run() == SearchStatus::NotFound) != reachable
+                })
+        }
     }
 }
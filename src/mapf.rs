@@ -0,0 +1,552 @@
+use map::{Agent, Map, MapPos};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+// наибольшее число узлов дерева ограничений, которое раскрывает CBS,
+// после чего используется приоритетное планирование
+pub const CBS_NODE_LIMIT: usize = 2000;
+
+// наибольшее число состояний, раскрываемых поисками путей одного агента
+// при приоритетном планировании и всеми поисками CBS вместе: если цель
+// недостижима из-за ограничений, поиск иначе перебрал бы все клетки карты
+// во все моменты времени; при исчерпании CBS уступает приоритетному
+// планированию, а оно считает путь агента ненайденным
+pub const EXPANSION_LIMIT: usize = 200_000;
+
+// способ, которым получено решение
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    // поиск на основе конфликтов, решение оптимально по сумме числа шагов,
+    // если найдено в пределах CBS_NODE_LIMIT и EXPANSION_LIMIT; веса клеток
+    // не учитываются, любой шаг и ожидание стоят одинаково
+    ConflictBased,
+    // последовательное планирование агентов в порядке их добавления
+    Prioritized,
+}
+
+// пути агентов без столкновений, клетка пути с индексом t -
+// положение агента в момент времени t, после окончания пути агент стоит на цели
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub paths: Vec<Vec<MapPos>>,
+    pub method: Method,
+}
+
+impl Solution {
+    // положение агента в момент времени
+    pub fn position(&self, agent: usize, time: usize) -> MapPos {
+        position(&self.paths[agent], time)
+    }
+
+    // время, за которое все агенты достигают целей
+    pub fn makespan(&self) -> usize {
+        self.paths.iter().map(|p| p.len() - 1).max().unwrap_or(0)
+    }
+
+    // сумма длин путей в шагах
+    pub fn cost(&self) -> usize {
+        self.paths.iter().map(|p| p.len() - 1).sum()
+    }
+}
+
+fn position(path: &[MapPos], time: usize) -> MapPos {
+    path[time.min(path.len() - 1)]
+}
+
+// клетки, через которые проходит встречный диагональный переход,
+// пересекающий переход из from в to; у шестиугольных клеток диагоналей нет,
+// а соседи (+1, -1) и (-1, +1) - обычные соседние клетки
+fn crossing(map: &Map, from: MapPos, to: MapPos) -> Option<(MapPos, MapPos)> {
+    if !map.topology().is_square() || from.x == to.x || from.y == to.y {
+        return None;
+    }
+    Some((MapPos::new(from.x, to.y), MapPos::new(to.x, from.y)))
+}
+
+// ограничения на перемещение одного агента
+#[derive(Debug, Clone, Default)]
+struct Constraints {
+    // клетка запрещена в момент времени
+    vertex: HashSet<(MapPos, usize)>,
+    // переход между клетками, завершающийся в момент времени, запрещён
+    edge: HashSet<(MapPos, MapPos, usize)>,
+    // клетка запрещена начиная с момента времени (на ней остался другой агент)
+    after: HashMap<MapPos, usize>,
+}
+
+impl Constraints {
+    fn allowed(&self, from: MapPos, to: MapPos, time: usize) -> bool {
+        !self.vertex.contains(&(to, time))
+            && !self.edge.contains(&(from, to, time))
+            && self.after.get(&to).is_none_or(|t| time < *t)
+    }
+
+    // последний момент, в который клетка запрещена, цель засчитывается
+    // только если после него агент может оставаться на ней
+    fn last(&self, pos: MapPos) -> usize {
+        self.vertex
+            .iter()
+            .filter(|(p, _)| *p == pos)
+            .map(|(_, t)| *t)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn horizon(&self) -> usize {
+        let vertex = self.vertex.iter().map(|(_, t)| *t);
+        let edge = self.edge.iter().map(|(_, _, t)| *t);
+        let after = self.after.values().cloned();
+        vertex.chain(edge).chain(after).max().unwrap_or(0)
+    }
+
+    // запрет пути другого агента, используется приоритетным планированием
+    fn reserve(&mut self, map: &Map, path: &[MapPos]) {
+        for (t, pos) in path.iter().enumerate() {
+            self.vertex.insert((*pos, t));
+            if t > 0 {
+                let from = path[t - 1];
+                self.edge.insert((*pos, from, t));
+                if let Some((a, b)) = crossing(map, from, *pos) {
+                    self.edge.insert((a, b, t));
+                    self.edge.insert((b, a, t));
+                }
+            }
+        }
+        let goal = path[path.len() - 1];
+        self.after.insert(goal, path.len() - 1);
+    }
+}
+
+// количество шагов до цели от каждой клетки (поиск в ширину от цели
+// по обратным переходам)
+fn distances(map: &Map, goal: MapPos) -> Vec<usize> {
+    let cols = map.cols();
+    let mut dist = vec![usize::MAX; map.rows() * cols];
+    let mut queue = VecDeque::new();
+    dist[goal.x * cols + goal.y] = 0;
+    queue.push_back(goal);
+    while let Some(pos) = queue.pop_front() {
+        let d = dist[pos.x * cols + pos.y];
+        for next in map.predecessors(pos) {
+            let i = next.x * cols + next.y;
+            if dist[i] == usize::MAX {
+                dist[i] = d + 1;
+                queue.push_back(next);
+            }
+        }
+    }
+    dist
+}
+
+// состояние поиска в пространстве-времени
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Node {
+    pos: MapPos,
+    time: usize,
+    estimate: usize,
+}
+
+// обратный порядок сравнения, при равной оценке раньше раскрываются
+// более поздние по времени состояния
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.time.cmp(&other.time))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// поиск пути одного агента с учётом ограничений (А* в пространстве-времени),
+// на каждом шаге агент переходит в соседнюю клетку или ждёт на месте;
+// каждое раскрытое состояние уменьшает budget, при его исчерпании путь не найден
+fn plan_agent(
+    map: &Map,
+    agent: &Agent,
+    dist: &[usize],
+    constraints: &Constraints,
+    budget: &mut usize,
+) -> Option<Vec<MapPos>> {
+    let cols = map.cols();
+    let h = |pos: MapPos| dist[pos.x * cols + pos.y];
+    if h(agent.start) == usize::MAX || constraints.after.contains_key(&agent.goal) {
+        return None;
+    }
+    // после последнего ограничения путь не длиннее числа клеток карты
+    let horizon = constraints.horizon() + map.rows() * map.cols();
+    let last = constraints.last(agent.goal);
+    let mut queue = BinaryHeap::new();
+    let mut parents = HashMap::new();
+    queue.push(Node {
+        pos: agent.start,
+        time: 0,
+        estimate: h(agent.start),
+    });
+    parents.insert((agent.start, 0), agent.start);
+    while let Some(node) = queue.pop() {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        if node.pos == agent.goal && node.time >= last {
+            let mut path = vec![node.pos];
+            let mut state = (node.pos, node.time);
+            while state.1 > 0 {
+                let parent = parents[&state];
+                path.push(parent);
+                state = (parent, state.1 - 1);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if node.time >= horizon {
+            continue;
+        }
+        let time = node.time + 1;
+        for next in map.neighbors(node.pos).chain(Some(node.pos)) {
+            if h(next) == usize::MAX
+                || !constraints.allowed(node.pos, next, time)
+                || parents.contains_key(&(next, time))
+            {
+                continue;
+            }
+            parents.insert((next, time), node.pos);
+            queue.push(Node {
+                pos: next,
+                time,
+                estimate: time + h(next),
+            });
+        }
+    }
+    None
+}
+
+// конфликт двух агентов
+#[derive(Debug, Clone, Copy)]
+enum Conflict {
+    // агенты в одной клетке
+    Vertex(usize, usize, MapPos, usize),
+    // агенты обмениваются клетками или пересекают диагонали,
+    // для каждого агента указан его переход
+    Edge(usize, usize, (MapPos, MapPos), (MapPos, MapPos), usize),
+}
+
+// первый по времени конфликт путей
+fn find_conflict(map: &Map, paths: &[Vec<MapPos>]) -> Option<Conflict> {
+    let makespan = paths.iter().map(|p| p.len()).max().unwrap_or(0);
+    for t in 0..makespan {
+        for i in 0..paths.len() {
+            for j in i + 1..paths.len() {
+                let (a, b) = (position(&paths[i], t), position(&paths[j], t));
+                if a == b {
+                    return Some(Conflict::Vertex(i, j, a, t));
+                }
+                if t == 0 {
+                    continue;
+                }
+                let (pa, pb) = (position(&paths[i], t - 1), position(&paths[j], t - 1));
+                let swap = pa == b && pb == a;
+                let cross =
+                    crossing(map, pa, a).is_some_and(|cells| cells == (pb, b) || cells == (b, pb));
+                if swap || cross {
+                    return Some(Conflict::Edge(i, j, (pa, a), (pb, b), t));
+                }
+            }
+        }
+    }
+    None
+}
+
+// узел дерева ограничений
+struct CbsNode {
+    constraints: Vec<Constraints>,
+    paths: Vec<Vec<MapPos>>,
+}
+
+// поиск на основе конфликтов, None если решение не найдено
+// за node_limit узлов и expansion_limit раскрытых состояний
+fn conflict_based(
+    map: &Map,
+    dist: &[Vec<usize>],
+    node_limit: usize,
+    expansion_limit: usize,
+) -> Option<Vec<Vec<MapPos>>> {
+    let agents = map.agents();
+    let constraints = vec![Constraints::default(); agents.len()];
+    let mut budget = expansion_limit;
+    let paths = agents
+        .iter()
+        .zip(dist)
+        .map(|(a, d)| plan_agent(map, a, d, &Constraints::default(), &mut budget))
+        .collect::<Option<Vec<_>>>()?;
+    let cost = paths.iter().map(|p| p.len() - 1).sum::<usize>();
+    let mut nodes = vec![CbsNode { constraints, paths }];
+    // очередь узлов по возрастанию суммы длин путей
+    let mut queue = BinaryHeap::new();
+    queue.push((-(cost as i64), 0));
+    while let Some((_, index)) = queue.pop() {
+        if nodes.len() > node_limit || budget == 0 {
+            return None;
+        }
+        let conflict = match find_conflict(map, &nodes[index].paths) {
+            Some(conflict) => conflict,
+            None => return Some(nodes.swap_remove(index).paths),
+        };
+        // каждый из агентов конфликта получает ограничение в отдельной ветви
+        let branches = match conflict {
+            Conflict::Vertex(i, j, pos, t) => {
+                [(i, Some((pos, t)), None), (j, Some((pos, t)), None)]
+            }
+            Conflict::Edge(i, j, a, b, t) => [
+                (i, None, Some((a.0, a.1, t))),
+                (j, None, Some((b.0, b.1, t))),
+            ],
+        };
+        for (agent, vertex, edge) in branches.iter() {
+            let mut constraints = nodes[index].constraints.clone();
+            constraints[*agent].vertex.extend(*vertex);
+            constraints[*agent].edge.extend(*edge);
+            let path = plan_agent(
+                map,
+                &agents[*agent],
+                &dist[*agent],
+                &constraints[*agent],
+                &mut budget,
+            );
+            let path = match path {
+                Some(path) => path,
+                None => continue,
+            };
+            let mut paths = nodes[index].paths.clone();
+            paths[*agent] = path;
+            let cost = paths.iter().map(|p| p.len() - 1).sum::<usize>();
+            queue.push((-(cost as i64), nodes.len()));
+            nodes.push(CbsNode { constraints, paths });
+        }
+    }
+    None
+}
+
+// приоритетное планирование: каждый агент обходит пути предыдущих
+fn prioritized(map: &Map, dist: &[Vec<usize>], expansion_limit: usize) -> Option<Vec<Vec<MapPos>>> {
+    let mut constraints = Constraints::default();
+    let mut paths: Vec<Vec<MapPos>> = Vec::with_capacity(dist.len());
+    for (agent, dist) in map.agents().iter().zip(dist) {
+        let mut budget = expansion_limit;
+        let path = plan_agent(map, agent, dist, &constraints, &mut budget)?;
+        constraints.reserve(map, &path);
+        paths.push(path);
+    }
+    Some(paths)
+}
+
+// планирование путей всех агентов карты без столкновений
+pub fn solve(map: &Map) -> Option<Solution> {
+    plan(map, CBS_NODE_LIMIT, EXPANSION_LIMIT)
+}
+
+fn plan(map: &Map, node_limit: usize, expansion_limit: usize) -> Option<Solution> {
+    let dist = map
+        .agents()
+        .iter()
+        .map(|a| distances(map, a.goal))
+        .collect::<Vec<_>>();
+    if let Some(paths) = conflict_based(map, &dist, node_limit, expansion_limit) {
+        return Some(Solution {
+            paths,
+            method: Method::ConflictBased,
+        });
+    }
+    prioritized(map, &dist, expansion_limit).map(|paths| Solution {
+        paths,
+        method: Method::Prioritized,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{Cell, Topology};
+    use std::cmp::Reverse;
+
+    // агенты заданы координатами начальной и целевой клеток
+    fn parse(text: &str, agents: &[[usize; 4]]) -> Map {
+        let mut map = format::from_text(text).unwrap();
+        for &[sx, sy, gx, gy] in agents {
+            assert!(map.add_agent(Agent::new(MapPos::new(sx, sy), MapPos::new(gx, gy))));
+        }
+        map
+    }
+
+    // пути начинаются и заканчиваются в клетках агентов, каждый шаг -
+    // переход к соседу или ожидание, агенты не встречаются в одной клетке,
+    // не обмениваются клетками и не пересекают диагонали друг друга
+    fn check(map: &Map, solution: &Solution) {
+        for (agent, path) in map.agents().iter().zip(&solution.paths) {
+            assert_eq!(path[0], agent.start);
+            assert_eq!(path[path.len() - 1], agent.goal);
+            for w in path.windows(2) {
+                assert!(w[0] == w[1] || map.neighbors(w[0]).any(|p| p == w[1]));
+            }
+        }
+        for t in 0..solution.makespan() + 1 {
+            for i in 0..solution.paths.len() {
+                for j in i + 1..solution.paths.len() {
+                    let (a, b) = (solution.position(i, t), solution.position(j, t));
+                    assert_ne!(a, b, "агенты {} и {} в момент {}", i, j, t);
+                    if t == 0 {
+                        continue;
+                    }
+                    let (pa, pb) = (solution.position(i, t - 1), solution.position(j, t - 1));
+                    assert!(pa != b || pb != a, "обмен {} и {} в момент {}", i, j, t);
+                    let diagonal = |p: MapPos, q: MapPos| p.x != q.x && p.y != q.y;
+                    if map.topology().is_square() && diagonal(pa, a) && diagonal(pb, b) {
+                        let crossed = (pb, b) == (MapPos::new(pa.x, a.y), MapPos::new(a.x, pa.y));
+                        let back = (b, pb) == (MapPos::new(pa.x, a.y), MapPos::new(a.x, pa.y));
+                        assert!(
+                            !crossed && !back,
+                            "пересечение {} и {} в момент {}",
+                            i,
+                            j,
+                            t
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // наименьшая сумма шагов двух агентов полным перебором совместных
+    // состояний, агент, отмеченный как завершивший путь, стоит на цели
+    fn optimal_cost(map: &Map) -> Option<usize> {
+        let (a, b) = (map.agents()[0], map.agents()[1]);
+        let moves = |pos: MapPos, done: bool| -> Vec<MapPos> {
+            if done {
+                vec![pos]
+            } else {
+                map.neighbors(pos).chain(Some(pos)).collect()
+            }
+        };
+        // в очереди клетки записаны парами координат, MapPos не упорядочен
+        let key = |p: MapPos| (p.x, p.y);
+        let pos = |(x, y)| MapPos::new(x, y);
+        let mut costs = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, key(a.start), key(b.start), false, false)));
+        while let Some(Reverse((cost, pa, pb, da, db))) = queue.pop() {
+            let (pa, pb) = (pos(pa), pos(pb));
+            if da && db {
+                return Some(cost);
+            }
+            if costs.contains_key(&(pa, pb, da, db)) {
+                continue;
+            }
+            costs.insert((pa, pb, da, db), cost);
+            // отметка о завершении пути ничего не стоит
+            if pa == a.goal && !da {
+                queue.push(Reverse((cost, key(pa), key(pb), true, db)));
+            }
+            if pb == b.goal && !db {
+                queue.push(Reverse((cost, key(pa), key(pb), da, true)));
+            }
+            let step = cost + !da as usize + !db as usize;
+            for na in moves(pa, da) {
+                for nb in moves(pb, db) {
+                    let swap = na == pb && nb == pa;
+                    let cross = crossing(map, pa, na)
+                        .is_some_and(|cells| cells == (pb, nb) || cells == (nb, pb));
+                    if na != nb && !swap && !cross {
+                        queue.push(Reverse((step, key(na), key(nb), da, db)));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn solutions_are_collision_free() {
+        let maps = vec![
+            // встречные агенты в открытой области и перекрёсток
+            parse(
+                "S....\n.....\n.....\n....F\n",
+                &[[1, 0, 1, 4], [1, 4, 1, 0], [0, 2, 3, 2], [3, 1, 0, 3]],
+            ),
+            // диагональные переходы навстречу друг другу
+            parse("S.\n.F\n", &[[0, 0, 1, 1], [0, 1, 1, 0]]),
+            // коридор с карманом в начале
+            parse("S####\n....F\n", &[[1, 0, 1, 4], [1, 4, 1, 0]]),
+        ];
+        for map in &maps {
+            check(map, &solve(map).unwrap());
+        }
+        let mut hex = maps[0].clone();
+        hex.set_topology(Topology::Hex);
+        check(&hex, &solve(&hex).unwrap());
+    }
+
+    #[test]
+    fn conflict_based_is_optimal() {
+        let maps = vec![
+            parse("S####\n....F\n", &[[1, 0, 1, 4], [1, 4, 1, 0]]),
+            parse("S##.##\n.....F\n", &[[1, 0, 1, 5], [1, 5, 1, 0]]),
+            parse("S..\n...\n..F\n", &[[1, 0, 1, 2], [0, 1, 2, 1]]),
+        ];
+        for map in &maps {
+            let solution = solve(map).unwrap();
+            check(map, &solution);
+            assert_eq!(solution.method, Method::ConflictBased);
+            assert_eq!(Some(solution.cost()), optimal_cost(map));
+        }
+    }
+
+    #[test]
+    fn limits_fall_back_to_prioritized() {
+        let map = parse("S....\n.....\n....F\n", &[[1, 0, 1, 4], [1, 4, 1, 0]]);
+        let solution = plan(&map, CBS_NODE_LIMIT, EXPANSION_LIMIT).unwrap();
+        assert_eq!(solution.method, Method::ConflictBased);
+        // пути без ограничений сталкиваются, дерево из одного узла не раскрывается
+        let solution = plan(&map, 1, EXPANSION_LIMIT).unwrap();
+        assert_eq!(solution.method, Method::Prioritized);
+        check(&map, &solution);
+        // бюджет на все поиски CBS меньше, чем нужно для путей обоих агентов
+        let solution = plan(&map, CBS_NODE_LIMIT, 8).unwrap();
+        assert_eq!(solution.method, Method::Prioritized);
+        check(&map, &solution);
+        assert!(plan(&map, CBS_NODE_LIMIT, 2).is_none());
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_solution() {
+        let mut map = parse("S.#..\n..#.F\n", &[[0, 0, 1, 1], [1, 0, 0, 4]]);
+        assert!(solve(&map).is_none());
+        map.set_cell(Cell::Passable, MapPos::new(0, 2));
+        check(&map, &solve(&map).unwrap());
+        // в коридоре агенты не могут разойтись
+        let corridor = parse("S...F\n", &[[0, 3, 0, 1], [0, 0, 0, 4]]);
+        assert!(solve(&corridor).is_none());
+    }
+
+    #[test]
+    fn hex_moves_do_not_cross() {
+        let mut map = format::from_text("S...\n....\n....\n...F\n").unwrap();
+        let (from, to) = (MapPos::new(2, 1), MapPos::new(1, 0));
+        assert_eq!(
+            crossing(&map, from, to),
+            Some((MapPos::new(2, 0), MapPos::new(1, 1)))
+        );
+        map.set_topology(Topology::Hex);
+        assert!(map.neighbors(from).any(|p| p == to));
+        assert_eq!(crossing(&map, from, to), None);
+        let mut constraints = Constraints::default();
+        constraints.reserve(&map, &[from, to]);
+        assert_eq!(constraints.edge.len(), 1);
+    }
+}
@@ -0,0 +1,242 @@
+use map::{Cell, Map, MapError, MapPos, PosState};
+use std::cell::RefCell;
+use std::collections::BinaryHeap;
+use std::f64;
+
+// путь, найденный запросом
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPath {
+    // достигнутая конечная клетка
+    pub goal: MapPos,
+    pub cost: f64,
+    // клетки пути от начальной к конечной
+    pub path: Vec<MapPos>,
+    // клетки, извлечённые из очереди
+    pub expanded: usize,
+}
+
+// рабочие массивы поиска, общие для запросов одного потока,
+// записи предыдущих запросов отличаются номером запроса
+struct Scratch {
+    query: u32,
+    // клетка найдена, раскрыта или является целью в запросе с данным номером
+    seen: Vec<u32>,
+    closed: Vec<u32>,
+    goal: Vec<u32>,
+    cost: Vec<f64>,
+    parent: Vec<usize>,
+    queue: BinaryHeap<PosState>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::new());
+}
+
+impl Scratch {
+    fn new() -> Self {
+        Self {
+            query: 0,
+            seen: Vec::new(),
+            closed: Vec::new(),
+            goal: Vec::new(),
+            cost: Vec::new(),
+            parent: Vec::new(),
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    // подготовка массивов к новому запросу без их очистки
+    fn prepare(&mut self, len: usize) {
+        if self.seen.len() < len {
+            self.seen.resize(len, 0);
+            self.closed.resize(len, 0);
+            self.goal.resize(len, 0);
+            self.cost.resize(len, 0f64);
+            self.parent.resize(len, 0);
+        }
+        self.query = self.query.wrapping_add(1);
+        // после переполнения номера старые записи могут совпасть с новыми
+        if self.query == 0 {
+            for stamp in self
+                .seen
+                .iter_mut()
+                .chain(self.closed.iter_mut())
+                .chain(self.goal.iter_mut())
+            {
+                *stamp = 0;
+            }
+            self.query = 1;
+        }
+        self.queue.clear();
+    }
+
+    // А* от начальной клетки до ближайшей из конечных
+    fn search(&mut self, map: &Map, start: MapPos, goals: &[MapPos]) -> Option<QueryPath> {
+        let cols = map.cols();
+        let index = |pos: MapPos| pos.x * cols + pos.y;
+        let heuristic = |pos: MapPos| {
+            goals
+                .iter()
+                .map(|g| map.estimate(pos, *g))
+                .fold(f64::INFINITY, f64::min)
+        };
+        self.prepare(map.rows() * cols);
+        let query = self.query;
+        for goal in goals {
+            self.goal[index(*goal)] = query;
+        }
+        let i = index(start);
+        self.seen[i] = query;
+        self.cost[i] = 0f64;
+        self.parent[i] = i;
+        self.queue.push(PosState {
+            pos: start,
+            cost: heuristic(start),
+        });
+        let mut expanded = 0;
+        while let Some(current) = self.queue.pop() {
+            let i = index(current.pos);
+            if self.closed[i] == query {
+                continue;
+            }
+            self.closed[i] = query;
+            expanded += 1;
+            if self.goal[i] == query {
+                let mut path = vec![current.pos];
+                let mut j = i;
+                while self.parent[j] != j {
+                    j = self.parent[j];
+                    path.push(MapPos::new(j / cols, j % cols));
+                }
+                path.reverse();
+                return Some(QueryPath {
+                    goal: current.pos,
+                    cost: self.cost[i],
+                    path,
+                    expanded,
+                });
+            }
+            for pos in map.neighbors(current.pos) {
+                let j = index(pos);
+                let cost = self.cost[i] + map.cost(current.pos, pos);
+                if self.closed[j] == query || (self.seen[j] == query && cost >= self.cost[j]) {
+                    continue;
+                }
+                self.seen[j] = query;
+                self.cost[j] = cost;
+                self.parent[j] = i;
+                self.queue.push(PosState {
+                    pos,
+                    cost: cost + heuristic(pos),
+                });
+            }
+        }
+        None
+    }
+}
+
+// кратчайший путь от start до ближайшей из клеток goals без изменения карты,
+// начальная и конечные клетки карты не учитываются; карта только читается,
+// поэтому одну карту можно использовать из многих потоков одновременно,
+// рабочие массивы переиспользуются запросами каждого потока
+pub fn shortest_path(
+    map: &Map,
+    start: MapPos,
+    goals: &[MapPos],
+) -> Result<Option<QueryPath>, MapError> {
+    for pos in Some(&start).into_iter().chain(goals) {
+        if !map.contains(*pos) {
+            return Err(MapError::OutOfBounds(*pos));
+        }
+    }
+    if map[start.x][start.y] == Cell::Impassable || goals.is_empty() {
+        return Ok(None);
+    }
+    Ok(SCRATCH.with(|scratch| scratch.borrow_mut().search(map, start, goals)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{SearchStatus, ShortestPath};
+    use search::BestFirst;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn map_can_be_shared_between_threads() {
+        assert_send_sync::<Map>();
+        assert_send_sync::<QueryPath>();
+    }
+
+    #[test]
+    fn agrees_with_shortest_path() {
+        let map = format::from_text("S..#....\n.#.#.##.\n.#...#F.\n...#....\n").unwrap();
+        let mut search = ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra()));
+        let expected = match search.run() {
+            SearchStatus::Found(cost) => cost,
+            _ => panic!("путь не найден"),
+        };
+        let found = shortest_path(&map, map.start(), map.finishes())
+            .unwrap()
+            .unwrap();
+        assert!((found.cost - expected).abs() < 1e-9);
+        assert_eq!(found.path[0], map.start());
+        assert_eq!(found.path[found.path.len() - 1], map.finishes()[0]);
+        // повторный запрос на тех же массивах даёт тот же результат
+        assert_eq!(
+            shortest_path(&map, map.start(), map.finishes()).unwrap(),
+            Some(found)
+        );
+    }
+
+    #[test]
+    fn arbitrary_endpoints_and_errors() {
+        let map = format::from_text("S#.\n.#.\n..F\n").unwrap();
+        let found = shortest_path(&map, MapPos::new(0, 2), &[MapPos::new(0, 0)])
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.goal, MapPos::new(0, 0));
+        assert_eq!(found.path[0], MapPos::new(0, 2));
+        assert_eq!(
+            shortest_path(&map, MapPos::new(0, 0), &[MapPos::new(3, 0)]),
+            Err(MapError::OutOfBounds(MapPos::new(3, 0)))
+        );
+        assert_eq!(
+            shortest_path(&map, MapPos::new(0, 0), &[MapPos::new(0, 1)]),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn queries_from_many_threads() {
+        let map = Arc::new(Map::new(30, 30).unwrap());
+        let handles = (0..4)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    (0..30)
+                        .map(|y| {
+                            shortest_path(&map, MapPos::new(t, 0), &[MapPos::new(29, y)])
+                                .unwrap()
+                                .unwrap()
+                                .cost
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (t, handle) in handles.into_iter().enumerate() {
+            let costs = handle.join().unwrap();
+            for (y, cost) in costs.into_iter().enumerate() {
+                let dx = (29 - t) as f64;
+                let dy = y as f64;
+                let expected = dx.max(dy) - dx.min(dy) + dx.min(dy) * 2f64.sqrt();
+                assert!((cost - expected).abs() < 1e-9);
+            }
+        }
+    }
+}
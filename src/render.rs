@@ -0,0 +1,533 @@
+use cairo::{Context, Format, ImageSurface, SvgSurface};
+use color::Color;
+use flow::FlowField;
+use hex;
+use map::{Cell, Map, MapPos, PassageKind, Topology, MAX_WEIGHT};
+use mapf::Solution;
+use route::Route;
+use smooth::{self, Point};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use world::{Link, LinkKind};
+
+// размер клетки в пикселях при экспорте изображения
+pub const EXPORT_CELL_SIZE: usize = 20;
+
+// формат экспортируемого изображения
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("png") => Some(ImageFormat::Png),
+            Some("svg") => Some(ImageFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+// ошибка экспорта изображения
+#[derive(Debug)]
+pub enum RenderError {
+    Format(String),
+    Cairo(String),
+    Io(io::Error),
+    CellSize(usize),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Format(path) => write!(
+                f,
+                "Неизвестный формат изображения: {}, поддерживаются PNG и SVG",
+                path
+            ),
+            RenderError::Cairo(err) => write!(f, "Ошибка cairo: {}", err),
+            RenderError::Io(err) => write!(f, "{}", err),
+            RenderError::CellSize(size) => write!(f, "Недопустимый размер клетки: {}", size),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+impl From<io::Error> for RenderError {
+    fn from(err: io::Error) -> Self {
+        RenderError::Io(err)
+    }
+}
+
+// отрисовка карты, исследованной области и пути
+pub struct Renderer<'a> {
+    map: &'a Map,
+    paths: Vec<&'a [MapPos]>,
+    curves: &'a [Vec<Point>],
+    route: Option<&'a Route>,
+    agents: Option<(&'a Solution, f64)>,
+    flow: Option<&'a FlowField>,
+    // переходы многоуровневой карты и номер отображаемого слоя
+    links: Option<(&'a [Link], usize)>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(map: &'a Map) -> Self {
+        Self {
+            map,
+            paths: Vec::new(),
+            curves: &[],
+            route: None,
+            agents: None,
+            flow: None,
+            links: None,
+        }
+    }
+
+    pub fn with_path(mut self, path: Option<&'a [MapPos]>) -> Self {
+        self.paths.extend(path);
+        self
+    }
+
+    pub fn with_paths(mut self, paths: &'a [Vec<MapPos>]) -> Self {
+        self.paths.extend(paths.iter().map(|p| p.as_slice()));
+        self
+    }
+
+    // сглаженные пути в координатах карты
+    pub fn with_curves(mut self, curves: &'a [Vec<Point>]) -> Self {
+        self.curves = curves;
+        self
+    }
+
+    pub fn with_route(mut self, route: Option<&'a Route>) -> Self {
+        self.route = route;
+        self
+    }
+
+    // пути агентов и их положение в момент времени,
+    // дробная часть времени задаёт положение между клетками
+    pub fn with_agents(mut self, solution: Option<&'a Solution>, time: f64) -> Self {
+        self.agents = solution.map(|s| (s, time));
+        self
+    }
+
+    pub fn with_flow(mut self, flow: Option<&'a FlowField>) -> Self {
+        self.flow = flow;
+        self
+    }
+
+    // карта - слой layer многоуровневой карты с переходами links
+    pub fn with_links(mut self, links: &'a [Link], layer: usize) -> Self {
+        self.links = Some((links, layer));
+        self
+    }
+
+    // цвет агента
+    fn agent_color(agent: usize) -> Color {
+        Color::from_hue(agent as f64 * 0.618034f64)
+    }
+
+    // размер клетки при отрисовке в области указанного размера,
+    // для шестиугольных клеток - ширина и высота шестиугольника
+    fn cell_size(&self, width: f64, height: f64) -> (f64, f64) {
+        let (cols, rows) = match self.map.topology() {
+            Topology::Square => (self.map.cols() as f64, self.map.rows() as f64),
+            Topology::Hex => hex::extent(self.map.rows(), self.map.cols()),
+        };
+        (width / cols, height / rows)
+    }
+
+    // точка в координатах карты на экране
+    fn to_screen(&self, p: &Point, cell_width: f64, cell_height: f64) -> (f64, f64) {
+        let row = match self.map.topology() {
+            Topology::Square => p.0,
+            // соседние строки шестиугольников перекрываются на четверть высоты
+            Topology::Hex => p.0 / hex::ROW_HEIGHT * 0.75f64,
+        };
+        ((p.1 + 0.5f64) * cell_width, (row + 0.5f64) * cell_height)
+    }
+
+    // центр клетки
+    fn center(&self, pos: MapPos, cell_width: f64, cell_height: f64) -> (f64, f64) {
+        self.to_screen(&smooth::point(self.map, pos), cell_width, cell_height)
+    }
+
+    // контур клетки, уменьшенный на inset с каждой стороны
+    fn outline(
+        &self,
+        context: &Context,
+        pos: MapPos,
+        cell_width: f64,
+        cell_height: f64,
+        inset: f64,
+    ) {
+        let (x, y) = self.center(pos, cell_width, cell_height);
+        let (w, h) = (cell_width / 2f64 - inset, cell_height / 2f64 - inset);
+        match self.map.topology() {
+            Topology::Square => context.rectangle(x - w, y - h, 2f64 * w, 2f64 * h),
+            Topology::Hex => {
+                context.move_to(x, y - h);
+                context.line_to(x + w, y - h / 2f64);
+                context.line_to(x + w, y + h / 2f64);
+                context.line_to(x, y + h);
+                context.line_to(x - w, y + h / 2f64);
+                context.line_to(x - w, y - h / 2f64);
+                context.close_path();
+            }
+        }
+    }
+
+    pub fn draw(&self, context: &Context, width: f64, height: f64) {
+        context.rectangle(0f64, 0f64, width, height);
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.fill();
+        self.draw_cells(context, width, height);
+        if let Some(flow) = self.flow {
+            self.draw_flow(context, width, height, flow);
+        }
+        self.draw_passages(context, width, height);
+        for path in &self.paths {
+            self.draw_path(context, width, height, path, &Color::yellow(), 3f64);
+        }
+        for curve in self.curves {
+            self.draw_curve(context, width, height, curve, &Color::yellow(), 3f64);
+        }
+        // участки маршрута через промежуточные клетки рисуются разными цветами
+        if let Some(route) = self.route {
+            let colors = [Color::yellow(), Color::cyan(), Color::magenta()];
+            for (i, leg) in route.legs.iter().enumerate() {
+                let color = &colors[i % colors.len()];
+                self.draw_path(context, width, height, &leg.path, color, 3f64);
+            }
+        }
+        self.draw_links(context, width, height);
+        self.draw_order(context, width, height);
+        self.draw_agents(context, width, height);
+    }
+
+    // отрисовка карты
+    fn draw_cells(&self, context: &Context, width: f64, height: f64) {
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let border = 1f64;
+        for i in 0..self.map.rows() {
+            for j in 0..self.map.cols() {
+                let color = match self.map[i][j] {
+                    Cell::Passable => {
+                        // чем больше вес клетки, тем она темнее
+                        let weight = self.map.weight(MapPos::new(i, j));
+                        let t = f64::from(weight - 1) / f64::from(MAX_WEIGHT - 1);
+                        Color::white().mix(&Color::brown(), 0.8f64 * t)
+                    }
+                    Cell::Impassable => Color::grey(),
+                    Cell::Start => Color::green(),
+                    Cell::Finish => Color::red(),
+                    Cell::Visited => Color::pale_blue(),
+                    Cell::InQueue => Color::pale_green(),
+                    Cell::Waypoint => Color::orange(),
+                };
+                context.set_source_rgb(color.red, color.green, color.blue);
+                self.outline(context, MapPos::new(i, j), cell_width, cell_height, border);
+                context.fill();
+            }
+        }
+    }
+
+    // поле направлений: градиент расстояния до цели и стрелки к следующей клетке
+    fn draw_flow(&self, context: &Context, width: f64, height: f64, flow: &FlowField) {
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let max = flow.max_distance().max(f64::EPSILON);
+        let length = cell_width.min(cell_height) * 0.3f64;
+        context.set_line_width(1f64);
+        for i in 0..self.map.rows() {
+            for j in 0..self.map.cols() {
+                let pos = MapPos::new(i, j);
+                let distance = flow.distance(pos);
+                if self.map[i][j] == Cell::Impassable || !distance.is_finite() {
+                    continue;
+                }
+                // чем дальше от цели, тем темнее клетка
+                context.set_source_rgba(0.0, 0.0, 0.5, 0.6f64 * distance / max);
+                self.outline(context, pos, cell_width, cell_height, 0f64);
+                context.fill();
+                if let Some(next) = flow.next(pos) {
+                    // на замкнутой карте следующая клетка может быть за краем
+                    let step = smooth::points(self.map, &[pos, next]);
+                    let (x, y) = self.to_screen(&step[0], cell_width, cell_height);
+                    let (nx, ny) = self.to_screen(&step[1], cell_width, cell_height);
+                    let norm = (nx - x).hypot(ny - y);
+                    let (ux, uy) = ((nx - x) / norm, (ny - y) / norm);
+                    let (tx, ty) = (x + ux * length, y + uy * length);
+                    context.set_source_rgb(0.0, 0.0, 0.0);
+                    context.move_to(x - ux * length, y - uy * length);
+                    context.line_to(tx, ty);
+                    // наконечник стрелки
+                    let head = length * 0.5f64;
+                    context.move_to(tx - (ux - uy) * head, ty - (uy + ux) * head);
+                    context.line_to(tx, ty);
+                    context.line_to(tx - (ux + uy) * head, ty - (uy - ux) * head);
+                    context.stroke();
+                }
+            }
+        }
+    }
+
+    // направленные клетки - стрелками по направлению, цвет зависит от вида клетки
+    fn draw_passages(&self, context: &Context, width: f64, height: f64) {
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let length = cell_width.min(cell_height) * 0.35f64;
+        context.set_line_width(2f64);
+        for (pos, passage) in self.map.passages() {
+            let color = match passage.kind {
+                PassageKind::Conveyor => Color::black(),
+                PassageKind::Ramp => Color::magenta(),
+                PassageKind::Slope => Color::brown(),
+            };
+            // у шестиугольников диагональные направления ведут к соседям в соседних строках
+            let (dx, dy) = match (self.map.topology(), passage.direction.vector()) {
+                (Topology::Hex, (dx, dy)) if dx != 0f64 => (dx * hex::ROW_HEIGHT, dy * 0.5f64),
+                (_, vector) => vector,
+            };
+            let p = smooth::point(self.map, pos);
+            let (x, y) = self.to_screen(&p, cell_width, cell_height);
+            let (nx, ny) = self.to_screen(&(p.0 + dx, p.1 + dy), cell_width, cell_height);
+            let norm = (nx - x).hypot(ny - y);
+            let (ux, uy) = ((nx - x) / norm, (ny - y) / norm);
+            let (tx, ty) = (x + ux * length, y + uy * length);
+            context.set_source_rgb(color.red, color.green, color.blue);
+            context.move_to(x - ux * length, y - uy * length);
+            context.line_to(tx, ty);
+            let head = length * 0.6f64;
+            context.move_to(tx - (ux - uy) * head, ty - (uy + ux) * head);
+            context.line_to(tx, ty);
+            context.line_to(tx - (ux + uy) * head, ty - (uy - ux) * head);
+            context.stroke();
+        }
+    }
+
+    // концы переходов на слое - кольцами с номером слоя другого конца,
+    // концы порталов на одном слое соединяются пунктиром
+    fn draw_links(&self, context: &Context, width: f64, height: f64) {
+        let (links, layer) = match self.links {
+            Some(links) => links,
+            None => return,
+        };
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let radius = cell_width.min(cell_height) * 0.35f64;
+        context.set_font_size(radius);
+        for link in links {
+            let color = match link.kind {
+                LinkKind::Stairs => Color::orange(),
+                LinkKind::Elevator => Color::cyan(),
+                LinkKind::Portal => Color::magenta(),
+            };
+            context.set_source_rgb(color.red, color.green, color.blue);
+            context.set_line_width(2f64);
+            for &(end, other) in &[(link.a, link.b), (link.b, link.a)] {
+                if end.layer != layer {
+                    continue;
+                }
+                let (x, y) = self.center(end.pos, cell_width, cell_height);
+                context.new_sub_path();
+                context.arc(x, y, radius, 0f64, 2f64 * PI);
+                context.stroke();
+                if other.layer != layer {
+                    let text = (other.layer + 1).to_string();
+                    context.move_to(
+                        x - radius * 0.3f64 * text.len() as f64,
+                        y + radius * 0.35f64,
+                    );
+                    context.show_text(&text);
+                }
+            }
+            if link.a.layer == layer && link.b.layer == layer {
+                let (x0, y0) = self.center(link.a.pos, cell_width, cell_height);
+                let (x1, y1) = self.center(link.b.pos, cell_width, cell_height);
+                context.set_line_width(1f64);
+                context.set_dash(&[4f64, 4f64], 0f64);
+                context.move_to(x0, y0);
+                context.line_to(x1, y1);
+                context.stroke();
+                context.set_dash(&[], 0f64);
+            }
+        }
+    }
+
+    // номера промежуточных клеток в порядке посещения
+    fn draw_order(&self, context: &Context, width: f64, height: f64) {
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let size = cell_width.min(cell_height) * 0.6f64;
+        context.set_font_size(size);
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        for (i, pos) in self.map.waypoints().iter().enumerate() {
+            let (x, y) = self.center(*pos, cell_width, cell_height);
+            let text = (i + 1).to_string();
+            context.move_to(x - size * 0.3f64 * text.len() as f64, y + size * 0.35f64);
+            context.show_text(&text);
+        }
+    }
+
+    // отрисовка агентов: цели - рамками, пути - тонкими линиями,
+    // сами агенты - кругами в начальной клетке или на пути в текущий момент
+    fn draw_agents(&self, context: &Context, width: f64, height: f64) {
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let radius = cell_width.min(cell_height) * 0.35f64;
+        for (i, agent) in self.map.agents().iter().enumerate() {
+            let color = Renderer::agent_color(i);
+            context.set_source_rgb(color.red, color.green, color.blue);
+            context.set_line_width(2f64);
+            self.outline(context, agent.goal, cell_width, cell_height, 3f64);
+            context.stroke();
+            let (x, y) = match self.agents {
+                Some((solution, time)) => {
+                    let path = &solution.paths[i];
+                    self.draw_path(context, width, height, path, &color, 1f64);
+                    let step = time.max(0f64).floor();
+                    let from = solution.position(i, step as usize);
+                    let to = solution.position(i, step as usize + 1);
+                    let points = smooth::points(self.map, &[from, to]);
+                    let (x0, y0) = self.to_screen(&points[0], cell_width, cell_height);
+                    let (x1, y1) = self.to_screen(&points[1], cell_width, cell_height);
+                    let t = time - step;
+                    (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t)
+                }
+                None => self.center(agent.start, cell_width, cell_height),
+            };
+            context.set_source_rgb(color.red, color.green, color.blue);
+            context.arc(x, y, radius, 0f64, 2f64 * PI);
+            context.fill();
+        }
+    }
+
+    // отрисовка пути
+    fn draw_path(
+        &self,
+        context: &Context,
+        width: f64,
+        height: f64,
+        path: &[MapPos],
+        color: &Color,
+        line_width: f64,
+    ) {
+        let points = smooth::points(self.map, path);
+        self.draw_curve(context, width, height, &points, color, line_width);
+    }
+
+    // сдвиги копий ломаной на замкнутой карте, при которых копия видна:
+    // ломаная через край выходит за карту и продолжается с другой стороны
+    fn copies(period: Option<f64>, min: f64, max: f64) -> Vec<f64> {
+        match period {
+            Some(period) => {
+                // с запасом в клетку по краям, чтобы не обрезать толстые линии
+                let first = ((-1f64 - max) / period).ceil() as i64;
+                let last = ((period - min) / period).floor() as i64;
+                (first..=last).map(|k| k as f64 * period).collect()
+            }
+            None => vec![0f64],
+        }
+    }
+
+    // отрисовка ломаной, заданной в координатах карты
+    fn draw_curve(
+        &self,
+        context: &Context,
+        width: f64,
+        height: f64,
+        points: &[Point],
+        color: &Color,
+        line_width: f64,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+        let (cell_width, cell_height) = self.cell_size(width, height);
+        let to_screen = |p: &Point| self.to_screen(p, cell_width, cell_height);
+        context.set_line_width(line_width);
+        context.set_source_rgb(color.red, color.green, color.blue);
+        let (rows, cols) = smooth::period(self.map);
+        let bounds = |axis: fn(&Point) -> f64| {
+            points
+                .iter()
+                .map(axis)
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                    (lo.min(v), hi.max(v))
+                })
+        };
+        let (top, bottom) = bounds(|p| p.0);
+        let (left, right) = bounds(|p| p.1);
+        for dx in Renderer::copies(rows, top, bottom) {
+            for dy in Renderer::copies(cols, left, right) {
+                let (x, y) = to_screen(&(points[0].0 + dx, points[0].1 + dy));
+                context.move_to(x, y);
+                for p in points.iter().skip(1) {
+                    let (x, y) = to_screen(&(p.0 + dx, p.1 + dy));
+                    context.line_to(x, y);
+                }
+                context.stroke();
+            }
+        }
+    }
+
+    // размер изображения в пикселях при экспорте, cell_size - ширина клетки,
+    // шестиугольные клетки рисуются правильными шестиугольниками,
+    // нулевой размер клетки и слишком большое изображение считаются ошибкой
+    pub fn export_size(&self, cell_size: usize) -> Result<(i32, i32), RenderError> {
+        let size = cell_size as f64;
+        let (width, height) = match self.map.topology() {
+            Topology::Square => (self.map.cols() as f64 * size, self.map.rows() as f64 * size),
+            Topology::Hex => {
+                let (cols, rows) = hex::extent(self.map.rows(), self.map.cols());
+                ((cols * size).ceil(), (rows * size / hex::ROW_HEIGHT).ceil())
+            }
+        };
+        let limit = f64::from(i32::MAX);
+        if cell_size == 0 || width > limit || height > limit {
+            return Err(RenderError::CellSize(cell_size));
+        }
+        Ok((width as i32, height as i32))
+    }
+
+    pub fn write_png(&self, file: &Path, width: i32, height: i32) -> Result<(), RenderError> {
+        let surface = ImageSurface::create(Format::ARgb32, width, height)
+            .map_err(|err| RenderError::Cairo(format!("{:?}", err)))?;
+        {
+            let context = Context::new(&surface);
+            self.draw(&context, f64::from(width), f64::from(height));
+        }
+        let mut file = File::create(file)?;
+        surface
+            .write_to_png(&mut file)
+            .map_err(|err| RenderError::Cairo(format!("{:?}", err)))
+    }
+
+    pub fn write_svg(&self, file: &Path, width: i32, height: i32) -> Result<(), RenderError> {
+        let surface = SvgSurface::new(f64::from(width), f64::from(height), file);
+        {
+            let context = Context::new(&surface);
+            self.draw(&context, f64::from(width), f64::from(height));
+        }
+        surface.finish();
+        Ok(())
+    }
+
+    // экспорт изображения, формат выбирается по расширению файла
+    pub fn export(&self, file: &Path, cell_size: usize) -> Result<(), RenderError> {
+        let (width, height) = self.export_size(cell_size)?;
+        match ImageFormat::from_path(file) {
+            Some(ImageFormat::Png) => self.write_png(file, width, height),
+            Some(ImageFormat::Svg) => self.write_svg(file, width, height),
+            None => Err(RenderError::Format(file.display().to_string())),
+        }
+    }
+}
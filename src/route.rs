@@ -0,0 +1,248 @@
+use flow::FlowField;
+use map::{Map, MapPos};
+use std::f64;
+
+// наибольшее число промежуточных клеток, для которого порядок обхода
+// подбирается точно, для большего числа используется эвристика
+pub const EXACT_WAYPOINTS: usize = 12;
+
+// участок маршрута между соседними клетками порядка обхода
+#[derive(Debug, Clone)]
+pub struct Leg {
+    pub from: MapPos,
+    pub to: MapPos,
+    pub length: f64,
+    pub path: Vec<MapPos>,
+}
+
+// маршрут от начальной клетки через промежуточные к ближайшей конечной
+#[derive(Debug, Clone)]
+pub struct Route {
+    // промежуточные клетки в порядке посещения
+    pub order: Vec<MapPos>,
+    pub legs: Vec<Leg>,
+}
+
+impl Route {
+    pub fn length(&self) -> f64 {
+        self.legs.iter().map(|l| l.length).sum()
+    }
+
+    // путь целиком, клетки на стыках участков не повторяются
+    pub fn path(&self) -> Vec<MapPos> {
+        let mut path = Vec::new();
+        for leg in &self.legs {
+            let skip = if path.is_empty() { 0 } else { 1 };
+            path.extend(leg.path.iter().skip(skip));
+        }
+        path
+    }
+}
+
+// ближайшая к корню поля конечная клетка и расстояние до неё
+fn nearest(field: &FlowField, finishes: &[MapPos]) -> Option<(MapPos, f64)> {
+    finishes
+        .iter()
+        .map(|f| (*f, field.distance(*f)))
+        .filter(|(_, cost)| cost.is_finite())
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+}
+
+// путь от корня поля из источника до клетки
+fn path(field: &FlowField, to: MapPos) -> Vec<MapPos> {
+    let mut path = field.path(to).unwrap_or_default();
+    path.reverse();
+    path
+}
+
+// поля расстояний от начальной и от каждой промежуточной клетки,
+// клетка с индексом 0 - начальная, k - промежуточная клетка k - 1
+struct Trees {
+    nodes: Vec<MapPos>,
+    trees: Vec<FlowField>,
+}
+
+impl Trees {
+    fn new(map: &Map) -> Self {
+        let mut nodes = vec![map.start()];
+        nodes.extend_from_slice(map.waypoints());
+        let trees = nodes
+            .iter()
+            .map(|n| FlowField::from_sources(map, &[*n]))
+            .collect();
+        Self { nodes, trees }
+    }
+
+    fn cost(&self, from: usize, to: usize) -> f64 {
+        self.trees[from].distance(self.nodes[to])
+    }
+
+    // длина маршрута с заданным порядком промежуточных клеток
+    // до ближайшей конечной клетки
+    fn length(&self, order: &[usize], end: &[f64]) -> f64 {
+        let mut length = 0f64;
+        let mut prev = 0;
+        for &k in order {
+            length += self.cost(prev, k);
+            prev = k;
+        }
+        length + end[prev]
+    }
+
+    // построение участков маршрута, None если какая-то клетка недостижима
+    fn route(&self, map: &Map, order: &[usize]) -> Option<Route> {
+        let mut legs = Vec::with_capacity(order.len() + 1);
+        let mut prev = 0;
+        for &k in order {
+            let length = self.cost(prev, k);
+            if !length.is_finite() {
+                return None;
+            }
+            legs.push(Leg {
+                from: self.nodes[prev],
+                to: self.nodes[k],
+                length,
+                path: path(&self.trees[prev], self.nodes[k]),
+            });
+            prev = k;
+        }
+        let (finish, length) = nearest(&self.trees[prev], map.finishes())?;
+        legs.push(Leg {
+            from: self.nodes[prev],
+            to: finish,
+            length,
+            path: path(&self.trees[prev], finish),
+        });
+        Some(Route {
+            order: order.iter().map(|k| self.nodes[*k]).collect(),
+            legs,
+        })
+    }
+}
+
+// маршрут с порядком промежуточных клеток, заданным на карте
+pub fn plan(map: &Map) -> Option<Route> {
+    let trees = Trees::new(map);
+    let order = (1..trees.nodes.len()).collect::<Vec<_>>();
+    trees.route(map, &order)
+}
+
+// маршрут с порядком промежуточных клеток, минимизирующим его длину:
+// точный для небольшого числа клеток, иначе эвристический
+pub fn optimize(map: &Map) -> Option<Route> {
+    let trees = Trees::new(map);
+    let n = trees.nodes.len();
+    // все клетки должны быть достижимы из начальной; из-за односторонних
+    // переходов некоторые порядки всё равно могут быть невозможны, их длина
+    // бесконечна, и маршрут строится, только если найден порядок конечной длины
+    if (1..n).any(|k| !trees.cost(0, k).is_finite()) {
+        return None;
+    }
+    let end = trees
+        .trees
+        .iter()
+        .map(|t| {
+            nearest(t, map.finishes())
+                .map(|f| f.1)
+                .unwrap_or(f64::INFINITY)
+        })
+        .collect::<Vec<_>>();
+    let order = if n - 1 <= EXACT_WAYPOINTS {
+        held_karp(&trees, &end)
+    } else {
+        two_opt(&trees, &end, nearest_neighbor(&trees))
+    };
+    trees.route(map, &order)
+}
+
+// динамическое программирование по подмножествам (алгоритм Хелда-Карпа)
+fn held_karp(trees: &Trees, end: &[f64]) -> Vec<usize> {
+    let n = trees.nodes.len() - 1;
+    if n == 0 {
+        return Vec::new();
+    }
+    let full = (1 << n) - 1;
+    // cost[mask * n + j] - длина пути из начала через клетки mask с окончанием в j
+    let mut cost = vec![f64::INFINITY; (full + 1) * n];
+    let mut parent = vec![n; (full + 1) * n];
+    for j in 0..n {
+        cost[(1 << j) * n + j] = trees.cost(0, j + 1);
+    }
+    for mask in 1..=full {
+        for j in 0..n {
+            let current = cost[mask * n + j];
+            if mask & (1 << j) == 0 || !current.is_finite() {
+                continue;
+            }
+            for k in 0..n {
+                if mask & (1 << k) != 0 {
+                    continue;
+                }
+                let next = (mask | (1 << k)) * n + k;
+                let candidate = current + trees.cost(j + 1, k + 1);
+                if candidate < cost[next] {
+                    cost[next] = candidate;
+                    parent[next] = j;
+                }
+            }
+        }
+    }
+    let mut last = (0..n)
+        .min_by(|a, b| {
+            let a = cost[full * n + a] + end[a + 1];
+            let b = cost[full * n + b] + end[b + 1];
+            a.partial_cmp(&b).unwrap()
+        })
+        .unwrap();
+    let mut order = Vec::with_capacity(n);
+    let mut mask = full;
+    while last < n {
+        order.push(last + 1);
+        let prev = parent[mask * n + last];
+        mask &= !(1 << last);
+        last = prev;
+    }
+    order.reverse();
+    order
+}
+
+// жадный порядок: каждый раз переход к ближайшей непосещённой клетке
+fn nearest_neighbor(trees: &Trees) -> Vec<usize> {
+    let mut rest = (1..trees.nodes.len()).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(rest.len());
+    let mut prev = 0;
+    while !rest.is_empty() {
+        let i = (0..rest.len())
+            .min_by(|a, b| {
+                let a = trees.cost(prev, rest[*a]);
+                let b = trees.cost(prev, rest[*b]);
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap();
+        prev = rest.swap_remove(i);
+        order.push(prev);
+    }
+    order
+}
+
+// улучшение порядка разворотом отрезков, пока длина маршрута уменьшается
+fn two_opt(trees: &Trees, end: &[f64], mut order: Vec<usize>) -> Vec<usize> {
+    let mut best = trees.length(&order, end);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..order.len() {
+            for j in i + 1..order.len() {
+                order[i..=j].reverse();
+                let length = trees.length(&order, end);
+                if length + 1e-9 < best {
+                    best = length;
+                    improved = true;
+                } else {
+                    order[i..=j].reverse();
+                }
+            }
+        }
+    }
+    order
+}
@@ -0,0 +1,409 @@
+use map::{Cell, GoalMode, Map, MapPos, PosState, SearchStatus};
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// статистика выполненного поиска
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchStats {
+    // клетки, извлечённые из очереди
+    pub expanded: usize,
+    // клетки, добавленные в очередь
+    pub pushed: usize,
+    // наибольший размер очереди
+    pub max_frontier: usize,
+    // клетки, до которых найден путь
+    pub visited: usize,
+}
+
+// флаг отмены поиска, копии токена разделяют один флаг,
+// поиск можно отменить из другого потока
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// ограничения поиска, None - без ограничения,
+// после исчерпания бюджета поиск возвращает лучший частичный путь
+#[derive(Debug, Clone, Default)]
+pub struct SearchBudget {
+    // наибольшее число раскрытых клеток
+    pub max_nodes: Option<usize>,
+    // время поиска с момента инициализации
+    pub time_limit: Option<Duration>,
+    pub cancel: Option<CancelToken>,
+}
+
+impl SearchBudget {
+    // бюджет исчерпан при данном числе раскрытых клеток и времени начала поиска
+    pub fn exhausted(&self, expanded: usize, started: Instant) -> bool {
+        matches!(self.max_nodes, Some(n) if expanded >= n)
+            || matches!(self.time_limit, Some(t) if started.elapsed() >= t)
+            || matches!(self.cancel, Some(ref c) if c.is_cancelled())
+    }
+}
+
+// событие одной итерации поиска
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum SearchEvent {
+    // клетка извлечена из очереди, cost - стоимость пути до неё
+    NodeExpanded {
+        pos: MapPos,
+        cost: f64,
+    },
+    // клетка добавлена в очередь через родительскую клетку
+    NodePushed {
+        pos: MapPos,
+        parent: MapPos,
+        cost: f64,
+    },
+    // найден более дешёвый путь до уже найденной клетки
+    CostImproved {
+        pos: MapPos,
+        parent: MapPos,
+        old_cost: f64,
+        cost: f64,
+    },
+    // достигнута конечная клетка
+    GoalReached {
+        pos: MapPos,
+        cost: f64,
+    },
+    // очередь пуста, поиск завершён
+    FrontierExhausted,
+}
+
+impl SearchEvent {
+    // изменение клеток карты, которое алгоритм сделал при этом событии
+    pub fn apply(&self, map: &mut Map) {
+        match *self {
+            SearchEvent::NodePushed { pos, .. } => map.set_cell(Cell::InQueue, pos),
+            SearchEvent::NodeExpanded { pos, .. } => map.set_cell(Cell::Visited, pos),
+            _ => (),
+        }
+    }
+}
+
+// пошаговый алгоритм поиска пути, используется ShortestPath,
+// поиск может выполняться в отдельном потоке
+pub trait SearchAlgorithm: Send {
+    // подготовка к поиску от начальной клетки карты
+    fn init(&mut self, map: &Map, goal_mode: GoalMode);
+
+    // одна итерация поиска, исследованные клетки помечаются на карте,
+    // произошедшие события добавляются в events
+    fn step(&mut self, map: &mut Map, events: &mut Vec<SearchEvent>) -> SearchStatus;
+
+    // достигнутые конечные клетки в порядке достижения
+    fn reached(&self) -> &[MapPos];
+
+    // стоимость найденного пути до клетки
+    fn cost(&self, pos: MapPos) -> Option<f64>;
+
+    // найденный путь до клетки, путь записывается от конца к началу
+    fn path(&self, goal: MapPos) -> Option<Vec<MapPos>>;
+
+    fn stats(&self) -> SearchStats;
+
+    // клетка с найденным путём, ближайшая к конечной клетке по эвристике,
+    // конец частичного пути при исчерпании бюджета поиска
+    fn closest(&self, _map: &Map) -> Option<MapPos> {
+        None
+    }
+}
+
+// приоритет клетки в очереди по карте, клетке, стоимости пути до неё
+// и номеру добавления в очередь, меньшие значения извлекаются раньше
+pub type Priority = fn(&Map, MapPos, f64, usize) -> f64;
+
+// поиск по первому наилучшему с очередью по приоритету
+pub struct BestFirst {
+    queue: BinaryHeap<PosState>,
+    // родительская клетка и стоимость пути до клетки
+    visited: HashMap<MapPos, PosState>,
+    goal_mode: GoalMode,
+    // достигнутые конечные клетки в порядке достижения
+    reached: Vec<MapPos>,
+    stats: SearchStats,
+    priority: Priority,
+    // пересмотр уже найденных клеток при обнаружении более дешёвого пути
+    reopen: bool,
+}
+
+impl BestFirst {
+    pub fn new(priority: Priority, reopen: bool) -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            visited: HashMap::new(),
+            goal_mode: GoalMode::Nearest,
+            reached: Vec::new(),
+            stats: SearchStats::default(),
+            priority,
+            reopen,
+        }
+    }
+
+    // поиск в ширину: клетки извлекаются в порядке добавления
+    pub fn breadth_first() -> Self {
+        BestFirst::new(|_, _, _, order| order as f64, false)
+    }
+
+    pub fn dijkstra() -> Self {
+        BestFirst::new(|_, _, cost, _| cost, true)
+    }
+
+    // А* с эвристикой - расстоянием до ближайшей конечной клетки
+    pub fn astar() -> Self {
+        BestFirst::new(|map, pos, cost, _| cost + map.finish_distance(pos), true)
+    }
+
+    // результат поиска после достижения очередной конечной клетки
+    fn goal_status(&self) -> SearchStatus {
+        match self.reached.first() {
+            Some(nearest) => match self.visited.get(nearest) {
+                Some(info) => SearchStatus::Found(info.cost),
+                None => SearchStatus::NotFound,
+            },
+            None => SearchStatus::NotFound,
+        }
+    }
+
+    fn push(&mut self, map: &Map, pos: MapPos, cost: f64) {
+        let priority = (self.priority)(map, pos, cost, self.stats.pushed);
+        self.queue.push(PosState {
+            pos,
+            cost: priority,
+        });
+        self.stats.pushed += 1;
+        self.stats.max_frontier = self.stats.max_frontier.max(self.queue.len());
+    }
+}
+
+impl SearchAlgorithm for BestFirst {
+    fn init(&mut self, map: &Map, goal_mode: GoalMode) {
+        self.queue.clear();
+        self.visited.clear();
+        self.reached.clear();
+        self.stats = SearchStats::default();
+        self.goal_mode = goal_mode;
+        let start = map.start();
+        self.push(map, start, 0f64);
+        self.visited.insert(
+            start,
+            PosState {
+                pos: start,
+                cost: 0f64,
+            },
+        );
+    }
+
+    fn step(&mut self, map: &mut Map, events: &mut Vec<SearchEvent>) -> SearchStatus {
+        let current = match self.queue.pop() {
+            Some(current) => current,
+            // в режиме всех целей часть конечных клеток может быть недостижима
+            None => {
+                events.push(SearchEvent::FrontierExhausted);
+                return self.goal_status();
+            }
+        };
+        // клетки вне карты или без найденного пути появляются,
+        // только если карта изменилась после инициализации
+        let current_cost = match self.visited.get(&current.pos) {
+            Some(info) if map.contains(current.pos) => info.cost,
+            _ => return SearchStatus::Searching,
+        };
+        self.stats.expanded += 1;
+        events.push(SearchEvent::NodeExpanded {
+            pos: current.pos,
+            cost: current_cost,
+        });
+        if map[current.pos.x][current.pos.y] == Cell::Finish && !self.reached.contains(&current.pos)
+        {
+            self.reached.push(current.pos);
+            events.push(SearchEvent::GoalReached {
+                pos: current.pos,
+                cost: current_cost,
+            });
+            if self.goal_mode == GoalMode::Nearest || self.reached.len() == map.finishes().len() {
+                return self.goal_status();
+            }
+        }
+        for pos in map.neighbors(current.pos) {
+            let cost = current_cost + map.cost(current.pos, pos);
+            if let Some(info) = self.visited.get(&pos) {
+                if !self.reopen || cost >= info.cost {
+                    continue;
+                }
+                events.push(SearchEvent::CostImproved {
+                    pos,
+                    parent: current.pos,
+                    old_cost: info.cost,
+                    cost,
+                });
+            }
+            self.push(map, pos, cost);
+            events.push(SearchEvent::NodePushed {
+                pos,
+                parent: current.pos,
+                cost,
+            });
+            self.visited.insert(
+                pos,
+                PosState {
+                    pos: current.pos,
+                    cost,
+                },
+            );
+            map.set_cell(Cell::InQueue, pos);
+        }
+        map.set_cell(Cell::Visited, current.pos);
+        SearchStatus::Searching
+    }
+
+    fn reached(&self) -> &[MapPos] {
+        &self.reached
+    }
+
+    fn cost(&self, pos: MapPos) -> Option<f64> {
+        self.visited.get(&pos).map(|info| info.cost)
+    }
+
+    fn path(&self, goal: MapPos) -> Option<Vec<MapPos>> {
+        let mut vec = vec![goal];
+        let mut parent = self.visited.get(&goal)?.pos;
+        while parent != vec[vec.len() - 1] {
+            // защита от цикла родительских клеток
+            if vec.len() > self.visited.len() {
+                return None;
+            }
+            vec.push(parent);
+            parent = self.visited.get(&parent)?.pos;
+        }
+        Some(vec)
+    }
+
+    fn stats(&self) -> SearchStats {
+        SearchStats {
+            visited: self.visited.len(),
+            ..self.stats
+        }
+    }
+
+    // клетка, ближайшая к ещё не достигнутым конечным клеткам, при равной
+    // эвристике выбирается клетка с меньшей стоимостью пути
+    fn closest(&self, map: &Map) -> Option<MapPos> {
+        let mut goals = map
+            .finishes()
+            .iter()
+            .filter(|f| !self.reached.contains(f))
+            .cloned()
+            .collect::<Vec<_>>();
+        if goals.is_empty() {
+            goals = map.finishes().to_vec();
+        }
+        let distance = |pos: MapPos| {
+            goals
+                .iter()
+                .map(|g| map.estimate(pos, *g))
+                .fold(f64::INFINITY, f64::min)
+        };
+        self.visited
+            .iter()
+            .map(|(pos, info)| (distance(*pos), info.cost, *pos))
+            .min_by(|a, b| {
+                (a.0, a.1)
+                    .partial_cmp(&(b.0, b.1))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(_, _, pos)| pos)
+    }
+}
+
+// алгоритм в реестре: короткое имя для командной строки,
+// название для интерфейса и функция создания
+pub struct AlgorithmEntry {
+    pub name: &'static str,
+    pub title: &'static str,
+    create: Box<dyn Fn() -> Box<dyn SearchAlgorithm>>,
+}
+
+impl AlgorithmEntry {
+    pub fn create(&self) -> Box<dyn SearchAlgorithm> {
+        (self.create)()
+    }
+}
+
+// реестр алгоритмов поиска, из которого заполняется список алгоритмов
+// в интерфейсе и выбирается алгоритм в командной строке
+pub struct Registry {
+    entries: Vec<AlgorithmEntry>,
+}
+
+impl Registry {
+    // пустой реестр
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    // добавление алгоритма, алгоритм с тем же именем заменяется
+    pub fn register<F>(&mut self, name: &'static str, title: &'static str, create: F)
+    where
+        F: Fn() -> Box<dyn SearchAlgorithm> + 'static,
+    {
+        let entry = AlgorithmEntry {
+            name,
+            title,
+            create: Box::new(create),
+        };
+        match self.entries.iter().position(|e| e.name == name) {
+            Some(i) => self.entries[i] = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn entries(&self) -> &[AlgorithmEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AlgorithmEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn SearchAlgorithm>> {
+        self.get(name).map(|e| e.create())
+    }
+}
+
+// реестр со встроенными алгоритмами
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry.register("bfs", "Поиск в ширину", || {
+            Box::new(BestFirst::breadth_first())
+        });
+        registry.register("dijkstra", "Алгоритм Дейкстры", || {
+            Box::new(BestFirst::dijkstra())
+        });
+        registry.register("astar", "А*", || Box::new(BestFirst::astar()));
+        registry
+    }
+}
+
+// алгоритм по умолчанию
+pub const DEFAULT_ALGORITHM: &str = "astar";
@@ -0,0 +1,357 @@
+use hex;
+use map::{Cell, Map, MapPos, Topology};
+
+// точка пути в координатах карты (строка, столбец), центр квадратной клетки (i, j) -
+// точка (i, j), центры шестиугольных клеток - см. hex::plane
+pub type Point = (f64, f64);
+
+// количество итераций сглаживания кривой Чайкина
+const CHAIKIN_ITERATIONS: usize = 3;
+
+// способ обработки найденного пути
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    // путь без изменений
+    Raw,
+    // удаление промежуточных клеток на прямых участках
+    Reduce,
+    // удаление промежуточных клеток, между соседями которых есть прямая видимость
+    StringPull,
+    // натягивание нити и сглаживание углов кривой Чайкина
+    Chaikin,
+}
+
+impl Smoothing {
+    pub const ALL: [Smoothing; 4] = [
+        Smoothing::Raw,
+        Smoothing::Reduce,
+        Smoothing::StringPull,
+        Smoothing::Chaikin,
+    ];
+
+    // способ по короткому имени, используемому в командной строке
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Smoothing::Raw),
+            "reduce" => Some(Smoothing::Reduce),
+            "pull" => Some(Smoothing::StringPull),
+            "chaikin" => Some(Smoothing::Chaikin),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Smoothing::Raw => "Без сглаживания",
+            Smoothing::Reduce => "Сокращение точек",
+            Smoothing::StringPull => "Натягивание нити",
+            Smoothing::Chaikin => "Кривая Чайкина",
+        }
+    }
+}
+
+// центр клетки
+pub fn point(map: &Map, pos: MapPos) -> Point {
+    match map.topology() {
+        Topology::Square => (pos.x as f64, pos.y as f64),
+        Topology::Hex => hex::plane(pos),
+    }
+}
+
+// размер замкнутой карты в координатах точек по вертикали и горизонтали,
+// None для направлений, в которых края не соединены
+pub fn period(map: &Map) -> (Option<f64>, Option<f64>) {
+    let (wrap_rows, wrap_cols) = map.wraps();
+    let rows = match map.topology() {
+        Topology::Square => map.rows() as f64,
+        Topology::Hex => map.rows() as f64 * hex::ROW_HEIGHT,
+    };
+    (
+        Some(rows).filter(|_| wrap_rows),
+        Some(map.cols() as f64).filter(|_| wrap_cols),
+    )
+}
+
+// центры клеток пути; на замкнутой карте каждая точка берётся на той копии
+// карты, где она ближе к предыдущей, поэтому путь через край непрерывен
+pub fn points(map: &Map, path: &[MapPos]) -> Vec<Point> {
+    let (rows, cols) = period(map);
+    let closest = |v: f64, to: f64, period: Option<f64>| match period {
+        Some(period) => v - ((v - to) / period).round() * period,
+        None => v,
+    };
+    let mut points: Vec<Point> = Vec::with_capacity(path.len());
+    for pos in path {
+        let mut p = point(map, *pos);
+        if let Some(&(x, y)) = points.last() {
+            p = (closest(p.0, x, rows), closest(p.1, y, cols));
+        }
+        points.push(p);
+    }
+    points
+}
+
+// натягивание нити, прямая видимость определена только для квадратных клеток
+// незамкнутой карты, остальные пути не изменяются
+fn pull(map: &Map, path: &[MapPos]) -> Vec<MapPos> {
+    match map.topology() {
+        Topology::Square if !map.wrap() => string_pull(map, path),
+        _ => path.to_vec(),
+    }
+}
+
+// обработка пути выбранным способом, веса клеток не учитываются
+pub fn smooth(map: &Map, path: &[MapPos], smoothing: Smoothing) -> Vec<Point> {
+    match smoothing {
+        Smoothing::Raw => points(map, path),
+        Smoothing::Reduce => reduce(&points(map, path)),
+        Smoothing::StringPull => points(map, &pull(map, path)),
+        Smoothing::Chaikin => chaikin(&points(map, &pull(map, path)), CHAIKIN_ITERATIONS),
+    }
+}
+
+// длина ломаной
+pub fn length(points: &[Point]) -> f64 {
+    points
+        .windows(2)
+        .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+        .sum()
+}
+
+// прямая видимость между центрами клеток: отрезок не проходит через стены,
+// при прохождении через угол клеток хотя бы одна из соседних клеток должна быть
+// проходима, как и при диагональном переходе между соседями; направленные
+// клетки меняют правила и стоимость переходов, поэтому отрезок, задевающий
+// их, тоже считается невидимым и путь через них остаётся без изменений
+pub fn line_of_sight(map: &Map, from: MapPos, to: MapPos) -> bool {
+    let passable = |x: isize, y: isize| map[x as usize][y as usize] != Cell::Impassable;
+    let free = |x: isize, y: isize| {
+        passable(x, y) && map.passage(MapPos::new(x as usize, y as usize)) == Ok(None)
+    };
+    if !free(from.x as isize, from.y as isize) {
+        return false;
+    }
+    let (nx, ny) = (
+        (to.x as isize - from.x as isize).abs(),
+        (to.y as isize - from.y as isize).abs(),
+    );
+    let sx = (to.x as isize - from.x as isize).signum();
+    let sy = (to.y as isize - from.y as isize).signum();
+    let (mut x, mut y) = (from.x as isize, from.y as isize);
+    let (mut ix, mut iy) = (0, 0);
+    while ix < nx || iy < ny {
+        // сравнение расстояний до следующих границ клеток по строкам и столбцам
+        let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+        if decision == 0 {
+            if !passable(x + sx, y) && !passable(x, y + sy) {
+                return false;
+            }
+            x += sx;
+            y += sy;
+            ix += 1;
+            iy += 1;
+        } else if decision < 0 {
+            x += sx;
+            ix += 1;
+        } else {
+            y += sy;
+            iy += 1;
+        }
+        if !free(x, y) {
+            return false;
+        }
+    }
+    true
+}
+
+// натягивание нити: из каждой оставленной клетки путь идёт
+// к самой дальней клетке пути, видимой из неё
+pub fn string_pull(map: &Map, path: &[MapPos]) -> Vec<MapPos> {
+    if path.len() < 3 {
+        return path.to_vec();
+    }
+    let mut result = vec![path[0]];
+    let mut anchor = 0;
+    for i in 2..path.len() {
+        if !line_of_sight(map, path[anchor], path[i]) {
+            anchor = i - 1;
+            result.push(path[anchor]);
+        }
+    }
+    result.push(path[path.len() - 1]);
+    result
+}
+
+// сокращение точек: остаются только точки, в которых меняется направление
+pub fn reduce(points: &[Point]) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    // шаги между центрами шестиугольников дробные, поэтому сравнение приближённое
+    let turns = |a: Point, b: Point, c: Point| {
+        ((b.0 - a.0) - (c.0 - b.0)).abs() > 1e-9 || ((b.1 - a.1) - (c.1 - b.1)).abs() > 1e-9
+    };
+    let mut result = vec![points[0]];
+    for w in points.windows(3) {
+        if turns(w[0], w[1], w[2]) {
+            result.push(w[1]);
+        }
+    }
+    result.push(points[points.len() - 1]);
+    result
+}
+
+// сглаживание углов ломаной: каждый отрезок заменяется точками на его четвертях,
+// концы ломаной сохраняются
+pub fn chaikin(points: &[Point], iterations: usize) -> Vec<Point> {
+    let mut points = points.to_vec();
+    for _ in 0..iterations {
+        if points.len() < 3 {
+            break;
+        }
+        let mut next = Vec::with_capacity(points.len() * 2);
+        next.push(points[0]);
+        for w in points.windows(2) {
+            let (p, q) = (w[0], w[1]);
+            next.push((0.75 * p.0 + 0.25 * q.0, 0.75 * p.1 + 0.25 * q.1));
+            next.push((0.25 * p.0 + 0.75 * q.0, 0.25 * p.1 + 0.75 * q.1));
+        }
+        next.push(points[points.len() - 1]);
+        points = next;
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+    use map::{Direction, Passage, PassageKind, ShortestPath};
+    use search::BestFirst;
+
+    fn positions(cells: &[(usize, usize)]) -> Vec<MapPos> {
+        cells.iter().map(|&(x, y)| MapPos::new(x, y)).collect()
+    }
+
+    // пути, найденные разными алгоритмами на картах со стенами и узкими проходами
+    fn paths() -> Vec<(Map, Vec<MapPos>)> {
+        let maps = [
+            "S..#......\n.#.#.####.\n.#...#....\n.####.#.#.\n......#.#F\n",
+            "S#......\n.#.####.\n.#.#..#.\n...#F.#.\n####.##.\n........\n",
+            "S.........\n.########.\n..........\n#########.\nF.........\n",
+        ];
+        let mut paths = Vec::new();
+        for text in maps.iter() {
+            let map = format::from_text(text).unwrap();
+            let algorithms: [fn() -> BestFirst; 3] = [
+                BestFirst::breadth_first,
+                BestFirst::dijkstra,
+                BestFirst::astar,
+            ];
+            for algorithm in algorithms.iter() {
+                let mut search = ShortestPath::new(map.clone(), Box::new(algorithm()));
+                search.run();
+                let mut path = search.path().unwrap();
+                path.reverse();
+                paths.push((map.clone(), path));
+            }
+        }
+        paths
+    }
+
+    // отрезок между центрами клеток не задевает стен: проверяются точки
+    // отрезка, не попадающие на границы клеток
+    fn crosses_wall(map: &Map, from: Point, to: Point) -> bool {
+        (0..200).any(|k| {
+            let t = (k as f64 + 0.37) / 200f64;
+            let x = (from.0 + (to.0 - from.0) * t).round() as usize;
+            let y = (from.1 + (to.1 - from.1) * t).round() as usize;
+            map[x][y] == Cell::Impassable
+        })
+    }
+
+    #[test]
+    fn line_of_sight_stops_at_walls() {
+        let map = format::from_text("S.#.\n....\n.#.F\n").unwrap();
+        let sees = |a: (usize, usize), b: (usize, usize)| {
+            line_of_sight(&map, MapPos::new(a.0, a.1), MapPos::new(b.0, b.1))
+        };
+        assert!(sees((0, 0), (0, 1)));
+        assert!(sees((1, 0), (1, 3)));
+        assert!(!sees((0, 1), (0, 3)));
+        // угол у стены проходим, если вторая клетка у угла свободна
+        assert!(sees((0, 0), (2, 2)));
+        assert!(!sees((2, 0), (2, 2)));
+        assert!(!sees((0, 2), (1, 2)));
+        // через угол между двумя стенами не пройти
+        let corner = format::from_text("S#\n#F\n").unwrap();
+        assert!(!line_of_sight(
+            &corner,
+            MapPos::new(0, 0),
+            MapPos::new(1, 1)
+        ));
+    }
+
+    #[test]
+    fn string_pull_is_visible_and_shorter() {
+        for (map, path) in paths() {
+            let pulled = string_pull(&map, &path);
+            assert_eq!(pulled[0], path[0]);
+            assert_eq!(pulled[pulled.len() - 1], path[path.len() - 1]);
+            assert!(pulled.len() <= path.len());
+            for w in pulled.windows(2) {
+                assert!(line_of_sight(&map, w[0], w[1]));
+                assert!(!crosses_wall(&map, point(&map, w[0]), point(&map, w[1])));
+            }
+            assert!(length(&points(&map, &pulled)) <= length(&points(&map, &path)) + 1e-9);
+        }
+    }
+
+    #[test]
+    fn reduce_and_chaikin_keep_ends_and_length() {
+        for (map, path) in paths() {
+            let raw = points(&map, &path);
+            let reduced = reduce(&raw);
+            assert_eq!(reduced[0], raw[0]);
+            assert_eq!(reduced[reduced.len() - 1], raw[raw.len() - 1]);
+            assert!(reduced.iter().all(|p| raw.contains(p)));
+            // удаляются только точки на прямых участках
+            assert!((length(&reduced) - length(&raw)).abs() < 1e-9);
+            for smoothing in Smoothing::ALL.iter() {
+                let curve = smooth(&map, &path, *smoothing);
+                assert_eq!(curve[0], raw[0]);
+                assert_eq!(curve[curve.len() - 1], raw[raw.len() - 1]);
+                assert!(length(&curve) <= length(&raw) + 1e-9);
+            }
+            let pulled = points(&map, &string_pull(&map, &path));
+            let curve = chaikin(&pulled, CHAIKIN_ITERATIONS);
+            assert!(length(&curve) <= length(&pulled) + 1e-9);
+        }
+        let corner = [(0f64, 0f64), (0f64, 2f64), (2f64, 2f64)];
+        let curve = chaikin(&corner, 1);
+        assert_eq!(
+            curve,
+            vec![
+                (0f64, 0f64),
+                (0f64, 0.5),
+                (0f64, 1.5),
+                (0.5, 2f64),
+                (1.5, 2f64),
+                (2f64, 2f64)
+            ]
+        );
+        assert_eq!(reduce(&corner), corner.to_vec());
+    }
+
+    #[test]
+    fn pull_keeps_passages() {
+        let mut map = format::from_text("S....\n.....\n....F\n").unwrap();
+        let path = positions(&[(0, 0), (1, 1), (2, 2), (2, 3), (2, 4)]);
+        assert_eq!(string_pull(&map, &path), vec![path[0], path[4]]);
+        // прямой отрезок прошёл бы по конвейеру против его направления
+        let conveyor = Passage::new(PassageKind::Conveyor, Direction::W);
+        map.set_passage(MapPos::new(1, 2), Some(conveyor)).unwrap();
+        assert!(!map.allows(MapPos::new(1, 2), MapPos::new(1, 3)));
+        assert_eq!(string_pull(&map, &path), vec![path[0], path[2], path[4]]);
+    }
+}
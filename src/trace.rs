@@ -0,0 +1,286 @@
+use format::{self, FormatError};
+use map::{Map, MapPos, SearchStatus, ShortestPath};
+use search::SearchEvent;
+use serde_json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// версия формата трассы
+pub const TRACE_VERSION: u32 = 1;
+
+// первая строка трассы: исходная карта и имя алгоритма
+#[derive(Serialize, Deserialize)]
+struct TraceHeader {
+    version: u32,
+    algorithm: String,
+    map: serde_json::Value,
+}
+
+// строка трассы: событие с номером итерации поиска
+#[derive(Serialize, Deserialize)]
+struct TraceLine {
+    step: usize,
+    #[serde(flatten)]
+    event: SearchEvent,
+}
+
+// запись трассы поиска в формате JSON Lines: заголовок,
+// затем по одной строке на каждое событие поиска
+pub struct TraceRecorder<W: Write> {
+    writer: W,
+    step: usize,
+}
+
+impl<W: Write> TraceRecorder<W> {
+    // запись заголовка с картой до начала поиска
+    pub fn new(mut writer: W, map: &Map, algorithm: &str) -> Result<Self, FormatError> {
+        let header = TraceHeader {
+            version: TRACE_VERSION,
+            algorithm: algorithm.to_string(),
+            map: format::to_json_value(map)?,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
+        Ok(Self { writer, step: 0 })
+    }
+
+    // запись событий одной итерации поиска
+    pub fn record(&mut self, events: &[SearchEvent]) -> Result<(), FormatError> {
+        for event in events {
+            let line = TraceLine {
+                step: self.step,
+                event: *event,
+            };
+            serde_json::to_writer(&mut self.writer, &line)?;
+            writeln!(self.writer)?;
+        }
+        self.step += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, FormatError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// поиск пути с записью трассы в файл
+pub fn record(
+    search: &mut ShortestPath,
+    algorithm: &str,
+    path: &Path,
+) -> Result<SearchStatus, FormatError> {
+    search.map.clear_path();
+    let writer = BufWriter::new(File::create(path)?);
+    let mut recorder = TraceRecorder::new(writer, &search.map, algorithm)?;
+    search.init();
+    loop {
+        let status = search.next();
+        recorder.record(search.last_events())?;
+        if status != SearchStatus::Searching {
+            recorder.finish()?;
+            return Ok(status);
+        }
+    }
+}
+
+// прочитанная трасса: исходная карта и события по итерациям
+pub struct Trace {
+    pub map: Map,
+    pub algorithm: String,
+    pub steps: Vec<Vec<SearchEvent>>,
+}
+
+impl Trace {
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, FormatError> {
+        let mut lines = reader.lines();
+        let header: TraceHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(FormatError::Text("Пустой файл трассы".to_string())),
+        };
+        if header.version != TRACE_VERSION {
+            return Err(FormatError::Text(format!(
+                "Неподдерживаемая версия трассы: {} (последняя {})",
+                header.version, TRACE_VERSION
+            )));
+        }
+        let mut steps: Vec<Vec<SearchEvent>> = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line: TraceLine = serde_json::from_str(&line)?;
+            if line.step < steps.len().saturating_sub(1) {
+                return Err(FormatError::Text(format!(
+                    "Нарушен порядок итераций трассы: {}",
+                    line.step
+                )));
+            }
+            steps.resize(line.step + 1, Vec::new());
+            steps[line.step].push(line.event);
+        }
+        Ok(Self {
+            map: format::from_json_value(header.map)?,
+            algorithm: header.algorithm,
+            steps,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, FormatError> {
+        Trace::read(BufReader::new(File::open(path)?))
+    }
+}
+
+// воспроизведение трассы на карте без повторного запуска алгоритма
+pub struct Replay {
+    trace: Trace,
+    step: usize,
+    // родительские клетки по последним событиям трассы
+    parents: HashMap<MapPos, MapPos>,
+    // достигнутые конечные клетки и стоимость путей до них
+    reached: Vec<(MapPos, f64)>,
+}
+
+impl Replay {
+    pub fn new(trace: Trace) -> Self {
+        Self {
+            trace,
+            step: 0,
+            parents: HashMap::new(),
+            reached: Vec::new(),
+        }
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.trace.map
+    }
+
+    pub fn algorithm(&self) -> &str {
+        &self.trace.algorithm
+    }
+
+    // применение событий следующей итерации к карте
+    pub fn next(&mut self, map: &mut Map) -> SearchStatus {
+        if let Some(events) = self.trace.steps.get(self.step) {
+            for event in events {
+                match *event {
+                    SearchEvent::NodePushed { pos, parent, .. } => {
+                        self.parents.insert(pos, parent);
+                    }
+                    SearchEvent::GoalReached { pos, cost } => self.reached.push((pos, cost)),
+                    _ => (),
+                }
+                event.apply(map);
+            }
+            self.step += 1;
+        }
+        if self.step < self.trace.steps.len() {
+            return SearchStatus::Searching;
+        }
+        match self.reached.first() {
+            Some((_, cost)) => SearchStatus::Found(*cost),
+            None => SearchStatus::NotFound,
+        }
+    }
+
+    // пути до достигнутых конечных клеток, путь записывается от конца к началу
+    pub fn paths(&self) -> Vec<(MapPos, f64, Vec<MapPos>)> {
+        self.reached
+            .iter()
+            .map(|(goal, cost)| {
+                let mut path = vec![*goal];
+                let mut pos = *goal;
+                while let Some(parent) = self.parents.get(&pos) {
+                    path.push(*parent);
+                    pos = *parent;
+                }
+                (*goal, *cost, path)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map::GoalMode;
+    use search::BestFirst;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn trace_text(trace: &str) -> Result<Trace, FormatError> {
+        Trace::read(trace.as_bytes())
+    }
+
+    #[test]
+    fn replay_matches_recorded_search() {
+        let map = format::from_text("F.S...\n.##.#.\n.....F\n").unwrap();
+        let path = env::temp_dir().join(format!("trace-{}.jsonl", process::id()));
+        let mut search = ShortestPath::new(map.clone(), Box::new(BestFirst::dijkstra()));
+        search.set_goal_mode(GoalMode::All);
+        let status = record(&mut search, "dijkstra", &path).unwrap();
+        let trace = Trace::load(&path);
+        fs::remove_file(&path).unwrap();
+        let trace = trace.unwrap();
+        assert_eq!(trace.algorithm, "dijkstra");
+        assert_eq!(trace.map, map);
+
+        let mut replay = Replay::new(trace);
+        let mut replayed = replay.map().clone();
+        let mut steps = 1;
+        while replay.next(&mut replayed) == SearchStatus::Searching {
+            steps += 1;
+        }
+        assert!(replay.next(&mut replayed) == status);
+        assert_eq!(steps, search.stats().expanded);
+        assert_eq!(replayed, search.map);
+        assert_eq!(replay.paths(), search.paths());
+        assert_eq!(replay.paths().len(), 2);
+    }
+
+    #[test]
+    fn recorder_numbers_steps() {
+        let map = format::from_text("S.F\n").unwrap();
+        let mut recorder = TraceRecorder::new(Vec::new(), &map, "bfs").unwrap();
+        let event = SearchEvent::NodeExpanded {
+            pos: map.start(),
+            cost: 0f64,
+        };
+        recorder.record(&[event, event]).unwrap();
+        recorder.record(&[]).unwrap();
+        recorder.record(&[SearchEvent::FrontierExhausted]).unwrap();
+        let bytes = recorder.finish().unwrap();
+        let trace = Trace::read(&bytes[..]).unwrap();
+        assert_eq!(
+            trace.steps,
+            vec![
+                vec![event, event],
+                vec![],
+                vec![SearchEvent::FrontierExhausted]
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_traces_are_rejected() {
+        let map = format::from_text("S.F\n").unwrap();
+        let header = String::from_utf8(
+            TraceRecorder::new(Vec::new(), &map, "bfs")
+                .unwrap()
+                .finish()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(trace_text(&header).unwrap().steps.is_empty());
+        assert!(trace_text("").is_err());
+        assert!(trace_text(&header.replace("\"version\":1", "\"version\":2")).is_err());
+        let line = |step| format!("{{\"step\":{},\"event\":\"FrontierExhausted\"}}\n", step);
+        assert!(trace_text(&(header.clone() + &line(0) + &line(2))).is_ok());
+        assert!(trace_text(&(header.clone() + &line(2) + &line(0))).is_err());
+        assert!(trace_text(&(header + "{\"step\":0}\n")).is_err());
+    }
+}
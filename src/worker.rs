@@ -0,0 +1,117 @@
+use alternatives::{self, Alternative};
+use map::{Map, SearchStatus, ShortestPath};
+use search::{CancelToken, SearchEvent, SearchStats};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// число итераций поиска между сообщениями о ходе поиска
+pub const PROGRESS_STEPS: usize = 256;
+
+// сообщение от потока поиска
+pub enum WorkerMessage {
+    // события очередных итераций и статистика поиска на данный момент
+    Progress {
+        events: Vec<SearchEvent>,
+        stats: SearchStats,
+    },
+    // поиск завершён, контекст поиска возвращается вместе с результатом
+    Finished {
+        search: Box<ShortestPath>,
+        status: SearchStatus,
+    },
+}
+
+// поиск пути в отдельном потоке без задержек между итерациями,
+// при отмене или удалении поток останавливается на ближайшей итерации
+pub struct SearchWorker {
+    receiver: Receiver<WorkerMessage>,
+    cancelled: CancelToken,
+}
+
+impl SearchWorker {
+    // запуск поиска, контекст инициализируется в потоке
+    pub fn spawn(mut search: ShortestPath) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = CancelToken::new();
+        let token = cancelled.clone();
+        thread::spawn(move || {
+            search.init();
+            let mut events = Vec::new();
+            let mut steps = 0;
+            loop {
+                if token.is_cancelled() {
+                    return;
+                }
+                let status = search.next();
+                events.extend_from_slice(search.last_events());
+                steps += 1;
+                if status != SearchStatus::Searching {
+                    let stats = search.stats();
+                    // получатель мог быть удалён, тогда результат не нужен
+                    let _ = sender.send(WorkerMessage::Progress { events, stats });
+                    let _ = sender.send(WorkerMessage::Finished {
+                        search: Box::new(search),
+                        status,
+                    });
+                    return;
+                }
+                if steps % PROGRESS_STEPS == 0 {
+                    let events = mem::take(&mut events);
+                    let stats = search.stats();
+                    if sender
+                        .send(WorkerMessage::Progress { events, stats })
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.cancel();
+    }
+
+    // очередное сообщение без ожидания, None если сообщений пока нет
+    // или поток завершён
+    pub fn try_recv(&self) -> Option<WorkerMessage> {
+        match self.receiver.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
+
+impl Drop for SearchWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// поиск альтернативных путей в отдельном потоке, чтобы интерфейс не ждал
+// k_shortest после каждого поиска; поиск не прерывается, результат
+// удалённого получателя отбрасывается
+pub struct AlternativesWorker {
+    receiver: Receiver<Vec<Alternative>>,
+}
+
+impl AlternativesWorker {
+    pub fn spawn(map: Map, k: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(alternatives::k_shortest(&map, k));
+        });
+        Self { receiver }
+    }
+
+    // найденные пути без ожидания, None если поиск ещё не завершён
+    pub fn try_recv(&self) -> Option<Vec<Alternative>> {
+        self.receiver.try_recv().ok()
+    }
+}
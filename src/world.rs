@@ -0,0 +1,421 @@
+use map::{Cell, Map, MapError, MapPos};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::f64;
+use std::fmt;
+
+// стоимость подъёма или спуска по лестнице на один слой
+pub const STAIRS_COST: f64 = 2.0;
+// стоимость поездки на лифте между любыми слоями, включая ожидание
+pub const ELEVATOR_COST: f64 = 3.0;
+// стоимость перехода через портал
+pub const PORTAL_COST: f64 = 1.0;
+
+// клетка одного из слоёв
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WorldPos {
+    pub layer: usize,
+    pub pos: MapPos,
+}
+
+impl WorldPos {
+    pub fn new(layer: usize, pos: MapPos) -> Self {
+        Self { layer, pos }
+    }
+}
+
+// вид перехода между удалёнными клетками
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LinkKind {
+    // лестница между слоями, стоимость растёт с числом пройденных слоёв
+    Stairs,
+    // лифт с одинаковой стоимостью поездки на любой слой
+    Elevator,
+    // портал между любыми двумя клетками
+    Portal,
+}
+
+impl LinkKind {
+    // стоимость перехода между клетками a и b
+    pub fn cost(self, a: WorldPos, b: WorldPos) -> f64 {
+        match self {
+            LinkKind::Stairs => STAIRS_COST * a.layer.abs_diff(b.layer).max(1) as f64,
+            LinkKind::Elevator => ELEVATOR_COST,
+            LinkKind::Portal => PORTAL_COST,
+        }
+    }
+}
+
+// переход, проходимый в обе стороны
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub a: WorldPos,
+    pub b: WorldPos,
+    pub cost: f64,
+}
+
+impl Link {
+    // другой конец перехода, None если pos - не конец перехода
+    pub fn other(&self, pos: WorldPos) -> Option<WorldPos> {
+        if pos == self.a {
+            Some(self.b)
+        } else if pos == self.b {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+}
+
+// ошибка изменения многоуровневой карты
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldError {
+    Layer(usize),
+    Cell(MapError),
+    SameCell(WorldPos),
+    Cost(f64),
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorldError::Layer(layer) => write!(f, "Слой {} не существует", layer + 1),
+            WorldError::Cell(err) => write!(f, "{}", err),
+            WorldError::SameCell(pos) => write!(
+                f,
+                "Переход ведёт в ту же клетку ({}, {}) слоя {}",
+                pos.pos.x,
+                pos.pos.y,
+                pos.layer + 1
+            ),
+            WorldError::Cost(cost) => write!(f, "Недопустимая стоимость перехода: {}", cost),
+        }
+    }
+}
+
+impl Error for WorldError {}
+
+impl From<MapError> for WorldError {
+    fn from(err: MapError) -> Self {
+        WorldError::Cell(err)
+    }
+}
+
+// путь по слоям от начальной клетки до конечной
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldPath {
+    pub cost: f64,
+    pub path: Vec<WorldPos>,
+}
+
+impl WorldPath {
+    // участки пути на слое между переходами, соседние клетки участка - соседи на карте
+    pub fn segments(&self, layer: usize) -> Vec<Vec<MapPos>> {
+        let mut segments: Vec<Vec<MapPos>> = Vec::new();
+        let mut last: Option<WorldPos> = None;
+        for p in &self.path {
+            let adjacent = match last {
+                Some(l) => {
+                    l.layer == p.layer
+                        && l.pos.x.abs_diff(p.pos.x) <= 1
+                        && l.pos.y.abs_diff(p.pos.y) <= 1
+                }
+                None => false,
+            };
+            last = Some(*p);
+            if p.layer != layer {
+                continue;
+            }
+            match segments.last_mut() {
+                Some(segment) if adjacent => segment.push(p.pos),
+                _ => segments.push(vec![p.pos]),
+            }
+        }
+        segments
+    }
+}
+
+// состояние поиска по слоям с обратным порядком сравнения
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WorldState {
+    pos: WorldPos,
+    cost: f64,
+}
+
+impl Eq for WorldState {}
+
+impl Ord for WorldState {
+    fn cmp(&self, other: &WorldState) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for WorldState {
+    fn partial_cmp(&self, other: &WorldState) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// карта из нескольких слоёв, связанных лестницами, лифтами и порталами;
+// путь ведёт от начальной клетки первого слоя к конечным клеткам последнего
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct World {
+    layers: Vec<Map>,
+    links: Vec<Link>,
+}
+
+impl World {
+    pub fn new(map: Map) -> Self {
+        Self {
+            layers: vec![map],
+            links: Vec::new(),
+        }
+    }
+
+    pub fn layers(&self) -> &[Map] {
+        &self.layers
+    }
+
+    pub fn layer(&self, layer: usize) -> Option<&Map> {
+        self.layers.get(layer)
+    }
+
+    pub fn layer_mut(&mut self, layer: usize) -> Option<&mut Map> {
+        self.layers.get_mut(layer)
+    }
+
+    // добавление слоя над последним, возвращает номер нового слоя
+    pub fn add_layer(&mut self, map: Map) -> usize {
+        self.layers.push(map);
+        self.layers.len() - 1
+    }
+
+    // удаление слоя вместе с его переходами, единственный слой не удаляется
+    pub fn remove_layer(&mut self, layer: usize) -> Option<Map> {
+        if self.layers.len() < 2 || layer >= self.layers.len() {
+            return None;
+        }
+        self.links
+            .retain(|l| l.a.layer != layer && l.b.layer != layer);
+        // переходы верхних слоёв сдвигаются на слой вниз
+        for link in &mut self.links {
+            if link.a.layer > layer {
+                link.a.layer -= 1;
+            }
+            if link.b.layer > layer {
+                link.b.layer -= 1;
+            }
+        }
+        Some(self.layers.remove(layer))
+    }
+
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    // один слой без переходов - обычная карта
+    pub fn is_flat(&self) -> bool {
+        self.layers.len() == 1 && self.links.is_empty()
+    }
+
+    // переход со стоимостью по умолчанию для его вида
+    pub fn add_link(&mut self, kind: LinkKind, a: WorldPos, b: WorldPos) -> Result<(), WorldError> {
+        self.add_link_with_cost(kind, a, b, kind.cost(a, b))
+    }
+
+    pub fn add_link_with_cost(
+        &mut self,
+        kind: LinkKind,
+        a: WorldPos,
+        b: WorldPos,
+        cost: f64,
+    ) -> Result<(), WorldError> {
+        for end in &[a, b] {
+            match self.layers.get(end.layer) {
+                Some(map) if map.contains(end.pos) => (),
+                Some(_) => return Err(WorldError::Cell(MapError::OutOfBounds(end.pos))),
+                None => return Err(WorldError::Layer(end.layer)),
+            }
+        }
+        if a == b {
+            return Err(WorldError::SameCell(a));
+        }
+        if !(cost >= 0f64 && cost.is_finite()) {
+            return Err(WorldError::Cost(cost));
+        }
+        self.links.push(Link { kind, a, b, cost });
+        Ok(())
+    }
+
+    // удаление переходов с концом в клетке, false если их не было
+    pub fn remove_links(&mut self, pos: WorldPos) -> bool {
+        let len = self.links.len();
+        self.links.retain(|l| l.other(pos).is_none());
+        self.links.len() != len
+    }
+
+    pub fn start(&self) -> WorldPos {
+        WorldPos::new(0, self.layers[0].start())
+    }
+
+    pub fn goals(&self) -> Vec<WorldPos> {
+        let layer = self.layers.len() - 1;
+        self.layers[layer]
+            .finishes()
+            .iter()
+            .map(|f| WorldPos::new(layer, *f))
+            .collect()
+    }
+
+    // кратчайший путь от начальной клетки первого слоя до ближайшей
+    // из конечных клеток последнего
+    pub fn shortest_path(&self) -> Option<WorldPath> {
+        self.find(self.start(), &self.goals())
+    }
+
+    // алгоритм Дейкстры по клеткам всех слоёв: порталы делают оценку
+    // расстояния по прямой недопустимой, поэтому эвристика не используется
+    pub fn find(&self, start: WorldPos, goals: &[WorldPos]) -> Option<WorldPath> {
+        let passable = |p: &WorldPos| match self.layers.get(p.layer) {
+            Some(map) => map.contains(p.pos) && map[p.pos.x][p.pos.y] != Cell::Impassable,
+            None => false,
+        };
+        if !passable(&start) {
+            return None;
+        }
+        let mut jumps: HashMap<WorldPos, Vec<(WorldPos, f64)>> = HashMap::new();
+        for link in self
+            .links
+            .iter()
+            .filter(|l| passable(&l.a) && passable(&l.b))
+        {
+            jumps.entry(link.a).or_default().push((link.b, link.cost));
+            jumps.entry(link.b).or_default().push((link.a, link.cost));
+        }
+        let index = |p: WorldPos| p.pos.x * self.layers[p.layer].cols() + p.pos.y;
+        let mut cost = self
+            .layers
+            .iter()
+            .map(|m| vec![f64::INFINITY; m.rows() * m.cols()])
+            .collect::<Vec<_>>();
+        let mut parent = self
+            .layers
+            .iter()
+            .map(|m| vec![None; m.rows() * m.cols()])
+            .collect::<Vec<Vec<Option<WorldPos>>>>();
+        let mut queue = BinaryHeap::new();
+        cost[start.layer][index(start)] = 0f64;
+        queue.push(WorldState {
+            pos: start,
+            cost: 0f64,
+        });
+        while let Some(current) = queue.pop() {
+            let i = index(current.pos);
+            if current.cost > cost[current.pos.layer][i] {
+                continue;
+            }
+            if goals.contains(&current.pos) {
+                let mut path = vec![current.pos];
+                let mut pos = current.pos;
+                while let Some(p) = parent[pos.layer][index(pos)] {
+                    path.push(p);
+                    pos = p;
+                }
+                path.reverse();
+                return Some(WorldPath {
+                    cost: current.cost,
+                    path,
+                });
+            }
+            let map = &self.layers[current.pos.layer];
+            let steps = map.neighbors(current.pos.pos).map(|p| {
+                (
+                    WorldPos::new(current.pos.layer, p),
+                    map.cost(current.pos.pos, p),
+                )
+            });
+            let links = jumps.get(&current.pos).into_iter().flatten().cloned();
+            for (next, step) in steps.chain(links) {
+                let j = index(next);
+                let next_cost = current.cost + step;
+                if next_cost < cost[next.layer][j] {
+                    cost[next.layer][j] = next_cost;
+                    parent[next.layer][j] = Some(current.pos);
+                    queue.push(WorldState {
+                        pos: next,
+                        cost: next_cost,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format;
+
+    fn parse(text: &str) -> Map {
+        format::from_text(text).unwrap()
+    }
+
+    #[test]
+    fn stairs_connect_layers() {
+        let mut world = World::new(parse("S.#.\n..#F\n"));
+        let top = world.add_layer(parse("...F\n.S..\n"));
+        assert!(world.remove_layer(top).is_some());
+        world.add_layer(parse("S...\n...F\n"));
+        world
+            .add_link(
+                LinkKind::Stairs,
+                WorldPos::new(0, MapPos::new(0, 1)),
+                WorldPos::new(1, MapPos::new(0, 1)),
+            )
+            .unwrap();
+        let found = world.shortest_path().unwrap();
+        assert_eq!(found.path[0], world.start());
+        assert_eq!(found.path[found.path.len() - 1], world.goals()[0]);
+        // шаг до лестницы, подъём и два шага до конечной клетки
+        assert!((found.cost - (1f64 + STAIRS_COST + 2f64.sqrt() + 1f64)).abs() < 1e-9);
+        assert_eq!(
+            found.segments(0),
+            vec![vec![MapPos::new(0, 0), MapPos::new(0, 1)]]
+        );
+        assert_eq!(found.segments(1).len(), 1);
+        // без лестницы второй слой недостижим
+        assert!(world.remove_links(WorldPos::new(1, MapPos::new(0, 1))));
+        assert!(world.shortest_path().is_none());
+    }
+
+    #[test]
+    fn portals_shortcut_and_errors() {
+        let mut world = World::new(parse("S........F\n"));
+        let (a, b) = (
+            WorldPos::new(0, MapPos::new(0, 1)),
+            WorldPos::new(0, MapPos::new(0, 8)),
+        );
+        world.add_link(LinkKind::Portal, a, b).unwrap();
+        let found = world.shortest_path().unwrap();
+        assert!((found.cost - (2f64 + PORTAL_COST)).abs() < 1e-9);
+        assert_eq!(found.segments(0).len(), 2);
+        assert_eq!(
+            world.add_link(LinkKind::Portal, a, a),
+            Err(WorldError::SameCell(a))
+        );
+        assert_eq!(
+            world.add_link(LinkKind::Portal, a, WorldPos::new(1, MapPos::new(0, 0))),
+            Err(WorldError::Layer(1))
+        );
+        assert_eq!(
+            world.add_link(LinkKind::Portal, a, WorldPos::new(0, MapPos::new(1, 0))),
+            Err(WorldError::Cell(MapError::OutOfBounds(MapPos::new(1, 0))))
+        );
+    }
+}